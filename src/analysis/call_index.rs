@@ -0,0 +1,86 @@
+use rustc_hir::def_id::DefId;
+use rustc_hir::HirId;
+use rustc_middle::mir::{Local, TerminatorKind};
+use rustc_middle::ty::{GenericArgsRef, Interner, Ty, TyCtxt};
+use rustc_span::BytePos;
+use std::collections::HashMap;
+
+/// A call found in the MIR of a function.
+#[derive(Debug, Clone, Copy)]
+pub struct MirCall<'tcx> {
+    /// The called function, which is the trait method for calls to a trait method.
+    pub def_id: DefId,
+    /// The generic arguments of the call, in terms of the generics of the caller.
+    pub args: GenericArgsRef<'tcx>,
+    /// The return type of the called function, instantiated with the generic arguments.
+    pub ret_ty: Ty<'tcx>,
+    /// The local the returned value is stored in.
+    pub destination: Local,
+}
+
+/// Cache of the calls in the MIR of each function, so each body only has to be scanned once.
+/// The calls are indexed by the end of the span of the call, to which call expressions in the HIR are matched.
+#[derive(Default)]
+pub struct CallIndex<'tcx> {
+    bodies: HashMap<DefId, HashMap<BytePos, MirCall<'tcx>>>,
+}
+
+impl<'tcx> CallIndex<'tcx> {
+    /// Get the call made by a call expression in the MIR of the given function.
+    /// Returns `None` if no MIR is available or the call was not found (e.g. due to desugaring/optimizations).
+    pub fn get(
+        &mut self,
+        context: TyCtxt<'tcx>,
+        body_id: DefId,
+        call_id: HirId,
+    ) -> Option<MirCall<'tcx>> {
+        let span = context.hir_node(call_id).expect_expr().span;
+
+        self.bodies
+            .entry(body_id)
+            .or_insert_with(|| index_body(context, body_id))
+            .get(&span.hi())
+            .copied()
+    }
+}
+
+/// Index the calls to statically known functions in the MIR of a function by the end of their span.
+fn index_body(context: TyCtxt, body_id: DefId) -> HashMap<BytePos, MirCall> {
+    let mut res = HashMap::new();
+
+    if !context.is_mir_available(body_id) {
+        return res;
+    }
+
+    let mir = context.optimized_mir(body_id);
+
+    for block in mir.basic_blocks.iter() {
+        if let Some(terminator) = &block.terminator {
+            if let TerminatorKind::Call {
+                func,
+                fn_span,
+                destination,
+                ..
+            } = &terminator.kind
+            {
+                if let Some((def_id, args)) = func.const_fn_def() {
+                    let ret_ty = context
+                        .type_of_instantiated(def_id, args)
+                        .fn_sig(context)
+                        .output()
+                        .skip_binder();
+
+                    // Keep the first call with this span, like a linear scan of the blocks would find
+                    res.entry(fn_span.hi()).or_insert(MirCall {
+                        def_id,
+                        args,
+                        ret_ty,
+                        destination: destination.local,
+                    });
+                }
+            }
+        }
+    }
+
+    res
+}
@@ -1,5 +1,5 @@
-use crate::graph::{CallEdge, CallGraph, ChainGraph};
-use std::collections::HashMap;
+use crate::graph::{CallEdge, CallGraph, Chain, ChainGraph, ChainKind};
+use std::collections::{HashMap, HashSet};
 
 pub fn to_chains(graph: &CallGraph) -> ChainGraph {
     let mut new_graph = ChainGraph::new(graph.crate_name.clone());
@@ -8,31 +8,52 @@ pub fn to_chains(graph: &CallGraph) -> ChainGraph {
     let mut max_size: usize = 0;
     let mut total_size: usize = 0;
     let mut max_depth: usize = 0;
+    let mut option_count: usize = 0;
     // Loop over all edges (e.g. function calls)
     for edge in &graph.edges {
         // Start of a chain
-        if edge.is_error && !edge.propagates {
+        let Some(kind) = edge.chain_kind() else {
+            continue;
+        };
+        if !edge.propagates {
             let mut node_map: HashMap<usize, usize> = HashMap::new();
 
-            let (mut calls, depth) = get_chain_from_edge(graph, edge, &mut vec![], 1);
+            let (mut calls, depth) = get_chain_from_edge(graph, edge, kind, &mut HashSet::new(), 1);
+
             calls.push(edge.clone());
 
-            count += 1;
-            let size = calls.len();
-            total_size += size;
-            if size > max_size {
-                max_size = size;
-            }
-            if depth > max_depth {
-                max_depth = depth;
+            if kind == ChainKind::Option {
+                // Most calls returning an option don't propagate a `None` any further, so only count actual chains
+                if calls.len() == 1 {
+                    continue;
+                }
+                option_count += 1;
+            } else {
+                count += 1;
+                let size = calls.len();
+                total_size += size;
+                if size > max_size {
+                    max_size = size;
+                }
+                if depth > max_depth {
+                    max_depth = depth;
+                }
             }
 
+            new_graph.chains.push(Chain {
+                kind,
+                edges: calls.iter().map(CallEdge::id).collect(),
+            });
+
             for call in calls {
                 // If we've already added the node to the new graph, refer to that, otherwise, add a new node
                 let from = if node_map.contains_key(&call.from) {
                     node_map.get(&call.from).unwrap().clone()
                 } else {
-                    let id = new_graph.add_node(graph.nodes[call.from].label.clone());
+                    let id = new_graph.add_node(
+                        graph.nodes[call.from].label.clone(),
+                        graph.nodes[call.from].location.clone(),
+                    );
                     node_map.insert(call.from, id);
                     id
                 };
@@ -41,50 +62,74 @@ pub fn to_chains(graph: &CallGraph) -> ChainGraph {
                 let to = if node_map.contains_key(&call.to) {
                     node_map.get(&call.to).unwrap().clone()
                 } else {
-                    let id = new_graph.add_node(graph.nodes[call.to].label.clone());
+                    let id = new_graph.add_node(
+                        graph.nodes[call.to].label.clone(),
+                        graph.nodes[call.to].location.clone(),
+                    );
                     node_map.insert(call.to, id);
                     id
                 };
 
                 // Add the edge
-                new_graph.add_edge(from, to, call.ty);
+                new_graph.add_edge(
+                    from,
+                    to,
+                    Some(call.label()),
+                    kind,
+                    call.may_call,
+                    call.location.clone(),
+                );
             }
         }
     }
     let average_size = (total_size as f64) / (count as f64);
+    let origin_count = graph.nodes.iter().filter(|node| node.origin).count();
 
     println!();
     println!("There are {count} error propagation chains in this program.");
     println!("The biggest chain consists of {max_size} function calls.");
     println!("The longest error path consists of {max_depth} chained function calls.");
     println!("The average chain consists of {average_size} function calls.");
+    println!("There are {option_count} option propagation chains in this program.");
+    println!("Errors are constructed in {origin_count} places in this program.");
     println!();
 
     new_graph
 }
 
-fn get_chain_from_edge(graph: &CallGraph, from: &CallEdge, explored: &mut Vec<usize>, depth: usize) -> (Vec<CallEdge>, usize) {
+fn get_chain_from_edge(
+    graph: &CallGraph,
+    from: &CallEdge,
+    kind: ChainKind,
+    explored: &mut HashSet<usize>,
+    depth: usize,
+) -> (Vec<CallEdge>, usize) {
     let mut res = vec![];
+    // The ids of the edges in `res`
+    let mut added: HashSet<usize> = HashSet::new();
     let mut max_depth = depth;
 
-    explored.push(from.to);
+    explored.insert(from.to);
 
-    // Add all outgoing propagating error edges from the 'to' node to the list
+    // Add all outgoing propagating edges of the same kind from the 'to' node to the list
     // And do the same once for each node this edge calls to
     for edge in graph.get_outgoing_edges(from.to) {
-        if edge.is_error && edge.propagates {
-            if !explored.contains(&edge.to) && !res.contains(edge) && edge != from {
+        if edge.chain_kind() == Some(kind) && edge.propagates {
+            if !explored.contains(&edge.to) && !added.contains(&edge.id()) && edge != from {
                 // If we haven't had this edge yet, explore the node
                 res.push(edge.clone());
+                added.insert(edge.id());
 
-                let (chain, d) = get_chain_from_edge(graph, edge, explored, depth + 1);
+                let (chain, d) = get_chain_from_edge(graph, edge, kind, explored, depth + 1);
                 if d > max_depth {
                     max_depth = d;
                 }
+                added.extend(chain.iter().map(CallEdge::id));
                 res.extend(chain);
             } else {
                 // Otherwise just add the edge
                 res.push(edge.clone());
+                added.insert(edge.id());
             }
         }
     }
@@ -0,0 +1,105 @@
+use crate::analysis::types;
+use crate::graph::Combinator;
+use rustc_hir::def_id::DefId;
+use rustc_hir::{ExprKind, HirId, Node};
+use rustc_middle::ty::{TyCtxt, TyKind};
+use rustc_span::{sym, DesugaringKind};
+
+/// Methods of `Result` and `Option` that pass an error (or `None`) on in the value they return.
+/// `or` is not one of them, as it replaces the error with that of its argument.
+const COMBINATORS: [&str; 17] = [
+    "map",
+    "map_err",
+    "and_then",
+    "or_else",
+    "and",
+    "ok_or",
+    "ok_or_else",
+    "filter",
+    "inspect",
+    "inspect_err",
+    "as_ref",
+    "as_mut",
+    "as_deref",
+    "cloned",
+    "copied",
+    "flatten",
+    "transpose",
+];
+
+/// Methods of error handling crates (e.g. anyhow and eyre) that wrap an error with additional context.
+const CONTEXT_METHODS: [&str; 4] = ["context", "with_context", "wrap_err", "wrap_err_with"];
+
+/// The error handling crates that provide the context methods, through an extension trait (e.g. `anyhow::Context`).
+const CONTEXT_CRATES: [&str; 2] = ["anyhow", "eyre"];
+
+/// Get the combinators the value returned by a call flows through, such as `foo().map_err(AppError::Io)`,
+/// along with the error type after each of them.
+/// Returns `None` if the value is consumed by any other method (e.g. `unwrap` or `ok`), so its error is not passed on.
+pub fn get_combinators(context: TyCtxt, call_id: HirId) -> Option<Vec<Combinator>> {
+    let mut res = vec![];
+    let mut child_id = call_id;
+
+    for (parent_id, node) in context.hir().parent_iter(call_id) {
+        let Node::Expr(expr) = node else {
+            break;
+        };
+
+        // Look through `.await` and temporaries, as the value is still the same
+        if expr.span.is_desugaring(DesugaringKind::Await)
+            || matches!(expr.kind, ExprKind::DropTemps(_))
+        {
+            child_id = parent_id;
+            continue;
+        }
+
+        let ExprKind::MethodCall(segment, receiver, _args, _span) = expr.kind else {
+            break;
+        };
+        if receiver.hir_id != child_id {
+            break;
+        }
+
+        let typeck = context.typeck(parent_id.owner.def_id);
+        let name = segment.ident.as_str();
+        let is_combinator = typeck
+            .type_dependent_def_id(parent_id)
+            .is_some_and(|method_id| is_combinator(context, method_id, name));
+        if !is_combinator {
+            return None;
+        }
+
+        let ty = typeck.expr_ty(expr);
+        let (_ty, error) = types::get_error_or_type(context, ty);
+        res.push(Combinator {
+            name: String::from(name),
+            error,
+        });
+
+        child_id = parent_id;
+    }
+
+    Some(res)
+}
+
+/// Check whether a method is one of the combinators of `Result` or `Option`,
+/// or one of the context methods of an error handling crate, rather than a method of the same name on another type.
+fn is_combinator(context: TyCtxt, method_id: DefId, name: &str) -> bool {
+    if COMBINATORS.contains(&name) {
+        let Some(impl_id) = context.impl_of_method(method_id) else {
+            return false;
+        };
+        let TyKind::Adt(adt, _args) = context.type_of(impl_id).instantiate_identity().kind() else {
+            return false;
+        };
+
+        context.is_diagnostic_item(sym::Result, adt.did())
+            || context.is_diagnostic_item(sym::Option, adt.did())
+    } else if CONTEXT_METHODS.contains(&name) {
+        context.trait_of_item(method_id).is_some_and(|trait_id| {
+            CONTEXT_CRATES.contains(&context.crate_name(trait_id.krate).as_str())
+        })
+    } else {
+        false
+    }
+}
@@ -1,33 +1,56 @@
-use crate::graph::{CallEdge, CallGraph, CallNodeKind};
+use crate::analysis::call_index::CallIndex;
+use crate::graph::{CallEdge, CallGraph, CallNodeKind, Location};
 use rustc_hir::def::{DefKind, Res};
-use rustc_hir::def_id::{DefId, LOCAL_CRATE};
+use rustc_hir::def_id::{DefId, LocalDefId, LOCAL_CRATE};
 use rustc_hir::{
-    Block, Expr, ExprKind, HirId, ImplItemKind, Item, ItemKind, MatchSource, Pat, PatKind, QPath,
-    StmtKind, TyKind,
+    Block, Expr, ExprKind, HirId, ImplItemKind, ItemKind, MatchSource, Node, Pat, PatKind, QPath,
+    StmtKind, TraitItemKind, TyKind,
 };
-use rustc_middle::mir::TerminatorKind;
-use rustc_middle::ty::TyCtxt;
+use rustc_middle::ty::{GenericArgsRef, Instance, InstanceDef, TyCtxt};
 
-/// Create a call graph starting from the provided root node.
-pub fn create_call_graph_from_root(context: TyCtxt, item: &Item) -> CallGraph {
+/// Create a call graph starting from the provided root functions, joining them into a single graph.
+pub fn create_call_graph_from_roots<'tcx>(
+    context: TyCtxt<'tcx>,
+    call_index: &mut CallIndex<'tcx>,
+    roots: &[LocalDefId],
+) -> CallGraph {
     let mut graph = CallGraph::new(context.crate_name(LOCAL_CRATE).to_ident_string());
 
-    // Access the function
-    if let ItemKind::Fn(_sig, _gen, id) = item.kind {
-        // Create a node for the function
-        let node = CallNodeKind::local_fn(item.hir_id().owner.to_def_id(), item.hir_id());
-        let node_id = graph.add_node(&context.def_path_str(node.def_id()), node);
+    for root in roots {
+        graph = create_call_graph_from_root(context, call_index, *root, graph);
+    }
+
+    graph
+}
 
-        // Add edges/nodes for all functions called from within this function (and recursively do it for those functions as well)
-        graph = add_calls_from_function(context, node_id, id.hir_id, graph);
+/// Add the provided root function, and all functions called from it, to the graph.
+fn create_call_graph_from_root<'tcx>(
+    context: TyCtxt<'tcx>,
+    call_index: &mut CallIndex<'tcx>,
+    root: LocalDefId,
+    mut graph: CallGraph,
+) -> CallGraph {
+    let hir_id = context.local_def_id_to_hir_id(root);
+
+    // This root was already explored when it was called from a previous root
+    if graph.find_local_fn_node(hir_id).is_some() {
+        return graph;
     }
 
+    // Create a node for the function
+    let node = CallNodeKind::local_fn(root.to_def_id(), hir_id);
+    let node_id = add_node(context, &mut graph, node);
+
+    // Add edges/nodes for all functions called from within this function (and recursively do it for those functions as well)
+    graph = add_calls_from_function(context, call_index, node_id, hir_id, graph);
+
     graph
 }
 
 /// Retrieve all function calls within a function, and add the nodes and edges to the graph.
-fn add_calls_from_function(
-    context: TyCtxt,
+fn add_calls_from_function<'tcx>(
+    context: TyCtxt<'tcx>,
+    call_index: &mut CallIndex<'tcx>,
     from_node: usize,
     fn_id: HirId,
     mut graph: CallGraph,
@@ -38,22 +61,34 @@ fn add_calls_from_function(
     match node {
         rustc_hir::Node::Expr(expr) => {
             if let ExprKind::Block(block, _) = expr.kind {
-                graph = add_calls_from_block(context, from_node, block, graph);
+                graph = add_calls_from_block(context, call_index, from_node, block, graph);
             } else if let ExprKind::Closure(closure) = expr.kind {
-                graph = add_calls_from_function(context, from_node, closure.body.hir_id, graph);
+                graph = add_calls_from_function(
+                    context,
+                    call_index,
+                    from_node,
+                    closure.body.hir_id,
+                    graph,
+                );
             }
         }
         rustc_hir::Node::Block(block) => {
-            graph = add_calls_from_block(context, from_node, block, graph);
+            graph = add_calls_from_block(context, call_index, from_node, block, graph);
         }
         rustc_hir::Node::Item(item) => {
             if let ItemKind::Fn(_sig, _gen, id) = item.kind {
-                graph = add_calls_from_function(context, from_node, id.hir_id, graph);
+                graph = add_calls_from_function(context, call_index, from_node, id.hir_id, graph);
             }
         }
         rustc_hir::Node::ImplItem(item) => {
             if let ImplItemKind::Fn(_sig, id) = item.kind {
-                graph = add_calls_from_function(context, from_node, id.hir_id, graph);
+                graph = add_calls_from_function(context, call_index, from_node, id.hir_id, graph);
+            }
+        }
+        rustc_hir::Node::TraitItem(item) => {
+            // The default implementation of a trait method
+            if let TraitItemKind::Fn(_sig, rustc_hir::TraitFn::Provided(id)) = item.kind {
+                graph = add_calls_from_function(context, call_index, from_node, id.hir_id, graph);
             }
         }
         _ => {}
@@ -63,60 +98,210 @@ fn add_calls_from_function(
 }
 
 /// Retrieve all function calls within a block, and add the nodes and edges to the graph.
-fn add_calls_from_block(
-    context: TyCtxt,
+fn add_calls_from_block<'tcx>(
+    context: TyCtxt<'tcx>,
+    call_index: &mut CallIndex<'tcx>,
     from: usize,
     block: &Block,
     mut graph: CallGraph,
 ) -> CallGraph {
     // Get the function calls from within this block
-    let calls = get_function_calls_in_block(context, block, true);
+    let calls = get_function_calls_in_block(context, call_index, block, true);
 
-    // Add edges for all function calls
+    // Add edges for all function calls, and explore the local functions that were not encountered yet
     for (node_kind, call_id, add_edge, propagates) in calls {
-        match node_kind {
-            CallNodeKind::LocalFn(def_id, hir_id) => {
-                if let Some(node) = graph.find_local_fn_node(hir_id) {
-                    // We have already encountered this local function, so just add the edge
-                    if add_edge {
-                        graph.add_edge(CallEdge::new(from, node.id(), call_id, propagates));
-                    }
-                } else {
-                    // We have not yet explored this local function, so add new node and edge,
-                    // and explore it.
-                    let id = graph.add_node(&context.def_path_str(def_id), node_kind);
+        let edge = add_edge.then_some(|to| CallEdge::new(from, to, Some(call_id), propagates));
 
-                    if add_edge {
-                        graph.add_edge(CallEdge::new(from, id, call_id, propagates));
-                    }
+        for (id, _def_id, hir_id) in add_call(context, &mut graph, &node_kind, edge) {
+            graph = add_calls_from_function(context, call_index, id, hir_id, graph);
+        }
+    }
 
-                    graph = add_calls_from_function(context, id, hir_id, graph);
-                }
-            }
-            CallNodeKind::NonLocalFn(def_id) => {
-                if let Some(node) = graph.find_non_local_fn_node(def_id) {
-                    // We have already encountered this non-local function, so just add the edge
-                    if add_edge {
-                        graph.add_edge(CallEdge::new(from, node.id(), call_id, propagates));
-                    }
-                } else {
-                    // We have not yet explored this non-local function, so add new node and edge
-                    let id = graph.add_node(&context.def_path_str(node_kind.def_id()), node_kind);
+    graph
+}
 
-                    if add_edge {
-                        graph.add_edge(CallEdge::new(from, id, call_id, propagates));
-                    }
+/// Add the edge of a call to the graph (if any), adding a node for the called function if it has none yet.
+/// A new virtual call is linked to all implementations it may call.
+/// Returns the local functions that were added to the graph, of which the calls still have to be added by the builder.
+pub fn add_call(
+    context: TyCtxt,
+    graph: &mut CallGraph,
+    node_kind: &CallNodeKind,
+    edge: Option<impl FnOnce(usize) -> CallEdge>,
+) -> Vec<(usize, DefId, HirId)> {
+    let node = match node_kind {
+        CallNodeKind::LocalFn(_def_id, hir_id) => graph.find_local_fn_node(*hir_id),
+        CallNodeKind::NonLocalFn(def_id) => graph.find_non_local_fn_node(*def_id),
+        CallNodeKind::TraitFn(_) | CallNodeKind::VirtualFn(_) => graph.find_node(node_kind),
+        CallNodeKind::ErrorOrigin(..) | CallNodeKind::Summarized(_) => {
+            // Only created after building the graph, or when merging crate summaries
+            return vec![];
+        }
+    };
+
+    // We have already encountered this function, so just add the edge
+    if let Some(node) = node {
+        if let Some(edge) = edge {
+            graph.add_edge(edge(node.id()));
+        }
+        return vec![];
+    }
+
+    // We have not yet encountered this function, so add new node and edge
+    let id = add_node(context, graph, node_kind.clone());
+    let mut call_location = None;
+    if let Some(edge) = edge {
+        let edge = edge(id);
+        call_location = match edge.call_id {
+            Some(call_id) => Some(Location::new(context, context.hir().span(call_id))),
+            None => edge.location.clone(),
+        };
+        graph.add_edge(edge);
+    }
+
+    match *node_kind {
+        CallNodeKind::LocalFn(def_id, hir_id) => vec![(id, def_id, hir_id)],
+        CallNodeKind::VirtualFn(def_id) => {
+            add_virtual_call_targets(context, graph, id, def_id, call_location)
+        }
+        _ => vec![],
+    }
+}
+
+/// Add may-call edges from a virtual call to every local implementation of the trait method,
+/// which propagate whatever the implementation returns.
+/// The edges are located at the dynamic call they originate from, rather than at the implementations.
+/// Returns the implementations that were added to the graph, which still have to be explored.
+fn add_virtual_call_targets(
+    context: TyCtxt,
+    graph: &mut CallGraph,
+    from: usize,
+    trait_fn: DefId,
+    call_location: Option<Location>,
+) -> Vec<(usize, DefId, HirId)> {
+    let mut res = vec![];
+
+    for target in get_virtual_call_targets(context, trait_fn) {
+        let node_kind = get_node_kind_from_def_id(context, target);
+        let CallNodeKind::LocalFn(_def_id, hir_id) = node_kind else {
+            continue;
+        };
+
+        let id = if let Some(node) = graph.find_local_fn_node(hir_id) {
+            node.id()
+        } else {
+            let id = add_node(context, graph, node_kind);
+            res.push((id, target, hir_id));
+            id
+        };
+
+        let mut edge = CallEdge::new(from, id, None, true);
+        edge.may_call = true;
+        edge.location.clone_from(&call_location);
+        graph.add_edge(edge);
+    }
+
+    res
+}
+
+/// Get the local implementations a trait method may dispatch to, using class hierarchy analysis:
+/// the method of every local impl of the trait, or its default implementation if the impl does not override it.
+fn get_virtual_call_targets(context: TyCtxt, trait_fn: DefId) -> Vec<DefId> {
+    let Some(trait_id) = context.trait_of_item(trait_fn) else {
+        return vec![];
+    };
+
+    let mut res = vec![];
+
+    for impl_id in context.all_impls(trait_id) {
+        if !impl_id.is_local() {
+            continue;
+        }
+
+        let target = match context.impl_item_implementor_ids(impl_id).get(&trait_fn) {
+            Some(impl_fn) => *impl_fn,
+            None if trait_fn.is_local() && context.defaultness(trait_fn).has_value() => trait_fn,
+            None => continue,
+        };
+
+        if !res.contains(&target) {
+            res.push(target);
+        }
+    }
+
+    res
+}
+
+/// Add may-call edges from each call a closure is passed to, to that closure,
+/// as the called function calls the closure and its result may flow back through the call (e.g. `try_for_each` or `and_then`).
+/// The edges start at the caller rather than the called function, which is shared by every call to it.
+pub fn add_closure_calls(context: TyCtxt, graph: &mut CallGraph) {
+    let mut closure_calls = vec![];
+
+    for edge in &graph.edges {
+        let Some(call_id) = edge.call_id else {
+            continue;
+        };
+        let args = match context.hir_node(call_id).expect_expr().kind {
+            ExprKind::Call(_, args) | ExprKind::MethodCall(_, _, args, _) => args,
+            _ => continue,
+        };
+
+        for arg in args {
+            let Some(closure) = get_closure(context, arg) else {
+                continue;
+            };
+            if let Some(node) = graph.find_local_fn_node(context.local_def_id_to_hir_id(closure)) {
+                if !closure_calls.contains(&(edge.from, node.id(), call_id, edge.propagates)) {
+                    closure_calls.push((edge.from, node.id(), call_id, edge.propagates));
                 }
             }
         }
     }
 
-    graph
+    for (from, to, call_id, propagates) in closure_calls {
+        let mut edge = CallEdge::new(from, to, Some(call_id), propagates);
+        edge.may_call = true;
+        edge.location = Some(Location::new(context, context.hir().span(call_id)));
+        graph.add_edge(edge);
+    }
+}
+
+/// Get the closure an argument evaluates to, if it is a closure or a reference to one,
+/// or a local variable that is bound to one.
+fn get_closure(context: TyCtxt, expr: &Expr) -> Option<LocalDefId> {
+    match expr.kind {
+        ExprKind::Closure(closure) => Some(closure.def_id),
+        ExprKind::AddrOf(_borrow, _mut, exp) => get_closure(context, exp),
+        ExprKind::Path(QPath::Resolved(None, path)) => {
+            let Res::Local(binding) = path.res else {
+                return None;
+            };
+            let Node::LetStmt(stmt) = context.parent_hir_node(binding) else {
+                return None;
+            };
+            get_closure(context, stmt.init?)
+        }
+        _ => None,
+    }
+}
+
+/// Add a node for a function to the graph, returning its id.
+pub fn add_node(context: TyCtxt, graph: &mut CallGraph, node_kind: CallNodeKind) -> usize {
+    let def_id = node_kind.def_id();
+    let id = graph.add_node(&context.def_path_str(def_id), node_kind);
+
+    let node = &mut graph.nodes[id];
+    node.krate = context.crate_name(def_id.krate).to_string();
+    node.location = Some(Location::new(context, context.def_span(def_id)));
+
+    id
 }
 
 /// Retrieve a vec of all function calls made within the body of a block.
-fn get_function_calls_in_block(
-    context: TyCtxt,
+fn get_function_calls_in_block<'tcx>(
+    context: TyCtxt<'tcx>,
+    call_index: &mut CallIndex<'tcx>,
     block: &Block,
     is_fn: bool,
 ) -> Vec<(CallNodeKind, HirId, bool, bool)> {
@@ -127,15 +312,17 @@ fn get_function_calls_in_block(
     if let Some(exp) = block.expr {
         if let ExprKind::DropTemps(ex) = exp.kind {
             if let ExprKind::Block(b, _lbl) = ex.kind {
-                return get_function_calls_in_block(context, b, is_fn);
+                return get_function_calls_in_block(context, call_index, b, is_fn);
             }
         } else {
             if is_fn {
-                for (kind, id, add_edge, _) in get_function_calls_in_expression(context, exp) {
+                for (kind, id, add_edge, _) in
+                    get_function_calls_in_expression(context, call_index, exp)
+                {
                     res.push((kind, id, add_edge, true));
                 }
             } else {
-                res.extend(get_function_calls_in_expression(context, exp));
+                res.extend(get_function_calls_in_expression(context, call_index, exp));
             }
         }
     }
@@ -146,14 +333,14 @@ fn get_function_calls_in_block(
         match statement.kind {
             StmtKind::Let(stmt) => {
                 if let Some(exp) = stmt.init {
-                    res.extend(get_function_calls_in_expression(context, exp));
+                    res.extend(get_function_calls_in_expression(context, call_index, exp));
                 }
             }
             StmtKind::Item(_id) => {
                 // No function calls here
             }
             StmtKind::Expr(exp) | StmtKind::Semi(exp) => {
-                res.extend(get_function_calls_in_expression(context, exp));
+                res.extend(get_function_calls_in_expression(context, call_index, exp));
             }
         }
     }
@@ -163,8 +350,9 @@ fn get_function_calls_in_block(
 
 /// Retrieve a vec of all function calls made within an expression.
 #[allow(clippy::too_many_lines)]
-fn get_function_calls_in_expression(
-    context: TyCtxt,
+fn get_function_calls_in_expression<'tcx>(
+    context: TyCtxt<'tcx>,
+    call_index: &mut CallIndex<'tcx>,
     expr: &Expr,
 ) -> Vec<(CallNodeKind, HirId, bool, bool)> {
     let mut res: Vec<(CallNodeKind, HirId, bool, bool)> = vec![];
@@ -172,8 +360,7 @@ fn get_function_calls_in_expression(
     // Match the kind of expression
     match expr.kind {
         ExprKind::Call(func, args) => {
-            if let Some(def_id) = get_call_def_id(context, expr.hir_id) {
-                let node_kind = get_node_kind_from_def_id(context, def_id);
+            if let Some(node_kind) = get_call_node_kind(context, call_index, expr.hir_id) {
                 res.push((node_kind, expr.hir_id, true, false));
             } else if let ExprKind::Path(qpath) = func.kind {
                 if let Some((node_kind, _add_edge)) = get_node_kind_from_path(context, qpath) {
@@ -181,12 +368,11 @@ fn get_function_calls_in_expression(
                 }
             }
             for exp in args {
-                res.extend(get_function_calls_in_expression(context, exp));
+                res.extend(get_function_calls_in_expression(context, call_index, exp));
             }
         }
         ExprKind::MethodCall(_path, exp, args, _span) => {
-            if let Some(def_id) = get_call_def_id(context, expr.hir_id) {
-                let node_kind = get_node_kind_from_def_id(context, def_id);
+            if let Some(node_kind) = get_call_node_kind(context, call_index, expr.hir_id) {
                 res.push((node_kind, expr.hir_id, true, false));
             } else if let Some(def_id) = context
                 .typeck(expr.hir_id.owner.def_id)
@@ -203,30 +389,34 @@ fn get_function_calls_in_expression(
                     res.push((CallNodeKind::non_local_fn(def_id), expr.hir_id, true, false));
                 }
             }
-            res.extend(get_function_calls_in_expression(context, exp));
+            res.extend(get_function_calls_in_expression(context, call_index, exp));
             for exp in args {
-                res.extend(get_function_calls_in_expression(context, exp));
+                res.extend(get_function_calls_in_expression(context, call_index, exp));
             }
         }
         ExprKind::Match(exp, arms, src) => {
             match src {
                 MatchSource::TryDesugar(_hir) => {
-                    for (kind, id, add_edge, _) in get_function_calls_in_expression(context, exp) {
+                    for (kind, id, add_edge, _) in
+                        get_function_calls_in_expression(context, call_index, exp)
+                    {
                         res.push((kind, id, add_edge, true));
                     }
 
                     return res;
                 }
                 _ => {
-                    res.extend(get_function_calls_in_expression(context, exp));
+                    res.extend(get_function_calls_in_expression(context, call_index, exp));
                 }
             }
             for arm in arms {
-                res.extend(get_function_calls_in_expression(context, arm.body));
+                res.extend(get_function_calls_in_expression(
+                    context, call_index, arm.body,
+                ));
                 if let Some(guard) = arm.guard {
-                    res.extend(get_function_calls_in_expression(context, guard));
+                    res.extend(get_function_calls_in_expression(context, call_index, guard));
                 }
-                res.extend(get_function_calls_in_pattern(context, arm.pat));
+                res.extend(get_function_calls_in_pattern(context, call_index, arm.pat));
             }
         }
         ExprKind::Closure(closure) => {
@@ -240,61 +430,68 @@ fn get_function_calls_in_expression(
             let node = context.hir_node(block.hir_id);
             res.extend(get_function_calls_in_block(
                 context,
+                call_index,
                 node.expect_block(),
                 false,
             ));
         }
         ExprKind::Array(args) | ExprKind::Tup(args) => {
             for exp in args {
-                res.extend(get_function_calls_in_expression(context, exp));
+                res.extend(get_function_calls_in_expression(context, call_index, exp));
             }
         }
         ExprKind::Binary(_op, a, b) => {
-            res.extend(get_function_calls_in_expression(context, a));
-            res.extend(get_function_calls_in_expression(context, b));
+            res.extend(get_function_calls_in_expression(context, call_index, a));
+            res.extend(get_function_calls_in_expression(context, call_index, b));
         }
         ExprKind::Unary(_op, exp) => {
-            res.extend(get_function_calls_in_expression(context, exp));
+            res.extend(get_function_calls_in_expression(context, call_index, exp));
         }
         ExprKind::Lit(_lit) => {
             // No function calls here
         }
         ExprKind::Cast(exp, _ty) | ExprKind::Type(exp, _ty) => {
-            res.extend(get_function_calls_in_expression(context, exp));
+            res.extend(get_function_calls_in_expression(context, call_index, exp));
         }
         ExprKind::DropTemps(exp) | ExprKind::Become(exp) => {
-            res.extend(get_function_calls_in_expression(context, exp));
+            res.extend(get_function_calls_in_expression(context, call_index, exp));
         }
         ExprKind::Let(exp) => {
-            res.extend(get_function_calls_in_expression(context, exp.init));
+            res.extend(get_function_calls_in_expression(
+                context, call_index, exp.init,
+            ));
         }
         ExprKind::If(a, b, c) => {
-            res.extend(get_function_calls_in_expression(context, a));
-            res.extend(get_function_calls_in_expression(context, b));
+            res.extend(get_function_calls_in_expression(context, call_index, a));
+            res.extend(get_function_calls_in_expression(context, call_index, b));
             if let Some(exp) = c {
-                res.extend(get_function_calls_in_expression(context, exp));
+                res.extend(get_function_calls_in_expression(context, call_index, exp));
             }
         }
         ExprKind::Loop(block, _lbl, _src, _span) => {
-            res.extend(get_function_calls_in_block(context, block, false));
+            res.extend(get_function_calls_in_block(
+                context, call_index, block, false,
+            ));
         }
         ExprKind::Block(block, _lbl) => {
-            res.extend(get_function_calls_in_block(context, block, false));
+            res.extend(get_function_calls_in_block(
+                context, call_index, block, false,
+            ));
         }
         ExprKind::Assign(a, b, _span) => {
-            res.extend(get_function_calls_in_expression(context, a));
-            res.extend(get_function_calls_in_expression(context, b));
+            res.extend(get_function_calls_in_expression(context, call_index, a));
+            res.extend(get_function_calls_in_expression(context, call_index, b));
         }
         ExprKind::AssignOp(_op, a, b) => {
-            res.extend(get_function_calls_in_expression(context, a));
-            res.extend(get_function_calls_in_expression(context, b));
+            res.extend(get_function_calls_in_expression(context, call_index, a));
+            res.extend(get_function_calls_in_expression(context, call_index, b));
         }
         ExprKind::Field(exp, _ident) => {
-            res.extend(get_function_calls_in_expression(context, exp));
+            res.extend(get_function_calls_in_expression(context, call_index, exp));
         }
         ExprKind::Index(a, b, _span) => {
-            res.extend(get_function_calls_in_expression(context, a));
-            res.extend(get_function_calls_in_expression(context, b));
+            res.extend(get_function_calls_in_expression(context, call_index, a));
+            res.extend(get_function_calls_in_expression(context, call_index, b));
         }
         ExprKind::Path(path) => {
             if let Some((node_kind, add_edge)) = get_node_kind_from_path(context, path) {
@@ -302,11 +499,11 @@ fn get_function_calls_in_expression(
             }
         }
         ExprKind::AddrOf(_borrow, _mut, exp) => {
-            res.extend(get_function_calls_in_expression(context, exp));
+            res.extend(get_function_calls_in_expression(context, call_index, exp));
         }
         ExprKind::Break(_dest, opt) => {
             if let Some(exp) = opt {
-                res.extend(get_function_calls_in_expression(context, exp));
+                res.extend(get_function_calls_in_expression(context, call_index, exp));
             }
         }
         ExprKind::Continue(_dest) => {
@@ -314,7 +511,9 @@ fn get_function_calls_in_expression(
         }
         ExprKind::Ret(opt) => {
             if let Some(exp) = opt {
-                for (kind, id, add_edge, _) in get_function_calls_in_expression(context, exp) {
+                for (kind, id, add_edge, _) in
+                    get_function_calls_in_expression(context, call_index, exp)
+                {
                     res.push((kind, id, add_edge, true));
                 }
             }
@@ -327,17 +526,19 @@ fn get_function_calls_in_expression(
         }
         ExprKind::Struct(_path, args, base) => {
             for exp in args {
-                res.extend(get_function_calls_in_expression(context, exp.expr));
+                res.extend(get_function_calls_in_expression(
+                    context, call_index, exp.expr,
+                ));
             }
             if let Some(exp) = base {
-                res.extend(get_function_calls_in_expression(context, exp));
+                res.extend(get_function_calls_in_expression(context, call_index, exp));
             }
         }
         ExprKind::Repeat(exp, _len) => {
-            res.extend(get_function_calls_in_expression(context, exp));
+            res.extend(get_function_calls_in_expression(context, call_index, exp));
         }
         ExprKind::Yield(exp, _src) => {
-            res.extend(get_function_calls_in_expression(context, exp));
+            res.extend(get_function_calls_in_expression(context, call_index, exp));
         }
         ExprKind::Err(_err) => {
             // No function calls here
@@ -348,8 +549,9 @@ fn get_function_calls_in_expression(
 }
 
 /// Retrieve a vec of all function calls made from within a pattern (although I think it can never contain one).
-fn get_function_calls_in_pattern(
-    context: TyCtxt,
+fn get_function_calls_in_pattern<'tcx>(
+    context: TyCtxt<'tcx>,
+    call_index: &mut CallIndex<'tcx>,
     pat: &Pat,
 ) -> Vec<(CallNodeKind, HirId, bool, bool)> {
     let mut res: Vec<(CallNodeKind, HirId, bool, bool)> = vec![];
@@ -360,22 +562,24 @@ fn get_function_calls_in_pattern(
         }
         PatKind::Binding(_mode, _hir_id, _ident, opt_pat) => {
             if let Some(p) = opt_pat {
-                res.extend(get_function_calls_in_pattern(context, p));
+                res.extend(get_function_calls_in_pattern(context, call_index, p));
             }
         }
         PatKind::Struct(_path, fields, _other) => {
             for field in fields {
-                res.extend(get_function_calls_in_pattern(context, field.pat));
+                res.extend(get_function_calls_in_pattern(
+                    context, call_index, field.pat,
+                ));
             }
         }
         PatKind::TupleStruct(_path, pats, _pos) => {
             for p in pats {
-                res.extend(get_function_calls_in_pattern(context, p));
+                res.extend(get_function_calls_in_pattern(context, call_index, p));
             }
         }
         PatKind::Or(pats) => {
             for p in pats {
-                res.extend(get_function_calls_in_pattern(context, p));
+                res.extend(get_function_calls_in_pattern(context, call_index, p));
             }
         }
         PatKind::Path(_path) => {
@@ -383,35 +587,35 @@ fn get_function_calls_in_pattern(
         }
         PatKind::Tuple(pats, _pos) => {
             for p in pats {
-                res.extend(get_function_calls_in_pattern(context, p));
+                res.extend(get_function_calls_in_pattern(context, call_index, p));
             }
         }
         PatKind::Box(p) | PatKind::Deref(p) => {
-            res.extend(get_function_calls_in_pattern(context, p));
+            res.extend(get_function_calls_in_pattern(context, call_index, p));
         }
         PatKind::Ref(p, _mut) => {
-            res.extend(get_function_calls_in_pattern(context, p));
+            res.extend(get_function_calls_in_pattern(context, call_index, p));
         }
         PatKind::Lit(exp) => {
-            res.extend(get_function_calls_in_expression(context, exp));
+            res.extend(get_function_calls_in_expression(context, call_index, exp));
         }
         PatKind::Range(a, b, _end) => {
             if let Some(exp) = a {
-                res.extend(get_function_calls_in_expression(context, exp));
+                res.extend(get_function_calls_in_expression(context, call_index, exp));
             }
             if let Some(exp) = b {
-                res.extend(get_function_calls_in_expression(context, exp));
+                res.extend(get_function_calls_in_expression(context, call_index, exp));
             }
         }
         PatKind::Slice(pats1, opt_pat, pats2) => {
             for p in pats1 {
-                res.extend(get_function_calls_in_pattern(context, p));
+                res.extend(get_function_calls_in_pattern(context, call_index, p));
             }
             if let Some(p) = opt_pat {
-                res.extend(get_function_calls_in_pattern(context, p));
+                res.extend(get_function_calls_in_pattern(context, call_index, p));
             }
             for p in pats2 {
-                res.extend(get_function_calls_in_pattern(context, p));
+                res.extend(get_function_calls_in_pattern(context, call_index, p));
             }
         }
         PatKind::Err(_err) => {
@@ -446,7 +650,7 @@ fn get_node_kind_from_path(context: TyCtxt, qpath: QPath) -> Option<(CallNodeKin
 }
 
 /// Get the `CallNodeKind` from a given `DefId`.
-fn get_node_kind_from_def_id(context: TyCtxt, def_id: DefId) -> CallNodeKind {
+pub fn get_node_kind_from_def_id(context: TyCtxt, def_id: DefId) -> CallNodeKind {
     if let Some(local_id) = def_id.as_local() {
         let hir_id = context.local_def_id_to_hir_id(local_id);
         CallNodeKind::local_fn(def_id, hir_id)
@@ -455,25 +659,43 @@ fn get_node_kind_from_def_id(context: TyCtxt, def_id: DefId) -> CallNodeKind {
     }
 }
 
-/// Get the `DefId` of the called function using the `HirId` of the call.
-pub fn get_call_def_id(context: TyCtxt, call_id: HirId) -> Option<DefId> {
-    if !context.is_mir_available(call_id.owner.to_def_id()) {
-        return None;
-    }
+/// Get the `CallNodeKind` of the called function using the `HirId` of the call.
+/// Trait method calls are resolved to their implementation using the generic arguments of the call,
+/// and become a `VirtualFn` if the call is on a trait object, or a `TraitFn` if the implementation depends on a generic parameter.
+pub fn get_call_node_kind<'tcx>(
+    context: TyCtxt<'tcx>,
+    call_index: &mut CallIndex<'tcx>,
+    call_id: HirId,
+) -> Option<CallNodeKind> {
+    let caller = call_id.owner.to_def_id();
+    let call = call_index.get(context, caller, call_id)?;
 
-    let mir = context.optimized_mir(call_id.owner.to_def_id());
+    Some(get_called_node_kind(
+        context,
+        caller,
+        call.def_id,
+        call.args,
+    ))
+}
 
-    for block in mir.basic_blocks.iter() {
-        if let Some(terminator) = &block.terminator {
-            if let TerminatorKind::Call { func, fn_span, .. } = &terminator.kind {
-                if context.hir_node(call_id).expect_expr().span.hi() == fn_span.hi() {
-                    if let Some((def_id, _)) = func.const_fn_def() {
-                        return Some(def_id);
-                    }
-                }
-            }
-        }
+/// Get the `CallNodeKind` of a function called from the given caller with the given generic arguments,
+/// resolving trait methods to their implementation.
+pub fn get_called_node_kind<'tcx>(
+    context: TyCtxt<'tcx>,
+    caller: DefId,
+    def_id: DefId,
+    args: GenericArgsRef<'tcx>,
+) -> CallNodeKind {
+    if context.trait_of_item(def_id).is_none() {
+        return get_node_kind_from_def_id(context, def_id);
     }
 
-    None
+    let param_env = context.param_env_reveal_all_normalized(caller);
+    match Instance::resolve(context, param_env, def_id, args) {
+        Ok(Some(instance)) => match instance.def {
+            InstanceDef::Virtual(_def_id, _index) => CallNodeKind::virtual_fn(def_id),
+            _ => get_node_kind_from_def_id(context, instance.def_id()),
+        },
+        _ => CallNodeKind::trait_fn(def_id),
+    }
 }
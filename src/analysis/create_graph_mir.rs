@@ -0,0 +1,264 @@
+use crate::analysis::create_graph::{
+    add_call, add_node, get_called_node_kind, get_node_kind_from_def_id,
+};
+use crate::analysis::types;
+use crate::graph::{CallEdge, CallGraph, CallNodeKind, Location};
+use rustc_hir::def_id::{DefId, LocalDefId, LOCAL_CRATE};
+use rustc_hir::intravisit::{walk_expr, Visitor};
+use rustc_hir::{Expr, ExprKind, HirId, MatchSource, Node};
+use rustc_middle::mir::{AggregateKind, Rvalue, StatementKind, TerminatorKind};
+use rustc_middle::ty::{Ty, TyCtxt, TyKind};
+use rustc_span::{BytePos, DesugaringKind, Span};
+use std::collections::HashMap;
+
+/// Create a call graph starting from the provided root functions, joining them into a single graph.
+/// Unlike `create_graph`, the calls are taken from the MIR of each function, which includes
+/// the calls created by desugaring (e.g. operators, `for` loops, `Deref`, `Index` and `Drop`).
+pub fn create_call_graph_from_roots(context: TyCtxt, roots: &[LocalDefId]) -> CallGraph {
+    let mut graph = CallGraph::new(context.crate_name(LOCAL_CRATE).to_ident_string());
+
+    for root in roots {
+        graph = create_call_graph_from_root(context, *root, graph);
+    }
+
+    graph
+}
+
+/// Add the provided root function, and all functions called from it, to the graph.
+fn create_call_graph_from_root(
+    context: TyCtxt,
+    root: LocalDefId,
+    mut graph: CallGraph,
+) -> CallGraph {
+    let hir_id = context.local_def_id_to_hir_id(root);
+
+    // This root was already explored when it was called from a previous root
+    if graph.find_local_fn_node(hir_id).is_some() {
+        return graph;
+    }
+
+    // Create a node for the function
+    let node = CallNodeKind::local_fn(root.to_def_id(), hir_id);
+    let node_id = add_node(context, &mut graph, node);
+
+    // Add edges/nodes for all functions called from within this function (and recursively do it for those functions as well)
+    graph = add_calls_from_function(context, node_id, root.to_def_id(), graph);
+
+    graph
+}
+
+/// Retrieve all function calls within the MIR of a function, and add the nodes and edges to the graph.
+fn add_calls_from_function(
+    context: TyCtxt,
+    from: usize,
+    fn_id: DefId,
+    mut graph: CallGraph,
+) -> CallGraph {
+    // Get the function calls from within this function
+    let calls = get_function_calls_in_body(context, fn_id);
+
+    // Add edges for all function calls, and explore the local functions that were not encountered yet
+    for (node_kind, call_id, add_edge, propagates, location, ret_ty) in calls {
+        let edge = add_edge.then_some(|to| {
+            let mut edge = CallEdge::new(from, to, call_id, propagates);
+            edge.location = Some(location);
+
+            // Desugared calls have no expression to get the type from, so they are annotated using the MIR instead
+            if let Some(ret_ty) = ret_ty {
+                types::annotate_return_type(context, &mut edge, node_kind.def_id(), ret_ty);
+            }
+
+            edge
+        });
+
+        for (id, def_id, _hir_id) in add_call(context, &mut graph, &node_kind, edge) {
+            graph = add_calls_from_function(context, id, def_id, graph);
+        }
+    }
+
+    graph
+}
+
+/// A function call made within the MIR of a function, along with the HIR expression of the call (if any),
+/// whether to add an edge, whether it propagates, its location, and the type it returns if it has no HIR expression.
+type BodyCall<'tcx> = (
+    CallNodeKind,
+    Option<HirId>,
+    bool,
+    bool,
+    Location,
+    Option<Ty<'tcx>>,
+);
+
+/// Retrieve a vec of all function calls made within the MIR of a function.
+fn get_function_calls_in_body(context: TyCtxt, fn_id: DefId) -> Vec<BodyCall> {
+    let mut res = vec![];
+
+    if !context.is_mir_available(fn_id) {
+        return res;
+    }
+
+    let mir = context.optimized_mir(fn_id);
+    let expressions = get_expressions(context, fn_id);
+
+    for block in mir.basic_blocks.iter() {
+        // Closures and coroutines (e.g. the body of an async function) are separate functions, without an edge
+        for statement in &block.statements {
+            if let StatementKind::Assign(assign) = &statement.kind {
+                if let Rvalue::Aggregate(kind, _operands) = &assign.1 {
+                    if let AggregateKind::Closure(def_id, _)
+                    | AggregateKind::Coroutine(def_id, _)
+                    | AggregateKind::CoroutineClosure(def_id, _) = **kind
+                    {
+                        let location = Location::new(context, statement.source_info.span);
+                        let node_kind = get_node_kind_from_def_id(context, def_id);
+                        res.push((node_kind, None, false, false, location, None));
+                    }
+                }
+            }
+        }
+
+        let Some(terminator) = &block.terminator else {
+            continue;
+        };
+
+        match &terminator.kind {
+            TerminatorKind::Call {
+                func,
+                fn_span,
+                destination,
+                ..
+            } => {
+                // The calls of the try operator itself are represented by the propagates flag of its operand
+                if fn_span.is_desugaring(DesugaringKind::QuestionMark) {
+                    continue;
+                }
+
+                let Some((def_id, args)) = func.const_fn_def() else {
+                    continue;
+                };
+
+                let node_kind = get_called_node_kind(context, fn_id, def_id, args);
+                let call_id = get_call_expression(&expressions, *fn_span);
+                let propagates = call_id.is_some_and(|id| propagates(context, id));
+                let location = Location::new(context, *fn_span);
+                let ret_ty = call_id.is_none().then(|| destination.ty(mir, context).ty);
+                res.push((node_kind, call_id, true, propagates, location, ret_ty));
+            }
+            TerminatorKind::Drop { place, .. } => {
+                let ty = place.ty(mir, context).ty;
+                let TyKind::Adt(adt, _args) = ty.kind() else {
+                    continue;
+                };
+                let Some(destructor) = context.adt_destructor(adt.did()) else {
+                    continue;
+                };
+
+                let location = Location::new(context, terminator.source_info.span);
+                let node_kind = get_node_kind_from_def_id(context, destructor.did);
+                res.push((node_kind, None, true, false, location, None));
+            }
+            _ => {}
+        }
+    }
+
+    res
+}
+
+/// Find the HIR expression of a call from the span of the function call in the MIR.
+/// Calls and method calls are matched on the span of the call and the method name respectively,
+/// and desugared calls (e.g. operators) on the span of the expression they were desugared from.
+fn get_call_expression(expressions: &HashMap<BytePos, Vec<&Expr>>, fn_span: Span) -> Option<HirId> {
+    let candidates = expressions.get(&fn_span.hi())?;
+
+    candidates
+        .iter()
+        .find(|expr| match expr.kind {
+            ExprKind::Call(_func, _args) => expr.span == fn_span,
+            ExprKind::MethodCall(segment, _receiver, _args, _span) => {
+                segment.ident.span.lo() == fn_span.lo()
+            }
+            _ => false,
+        })
+        .or_else(|| candidates.iter().find(|expr| expr.span == fn_span))
+        .map(|expr| expr.hir_id)
+}
+
+/// Get all expressions in the body of a local function (excluding nested closures), grouped by the end of their span.
+fn get_expressions(context: TyCtxt, fn_id: DefId) -> HashMap<BytePos, Vec<&Expr>> {
+    let mut collector = ExpressionCollector {
+        expressions: HashMap::new(),
+    };
+
+    if let Some(local_id) = fn_id.as_local() {
+        if let Some(body_id) = context.hir().maybe_body_owned_by(local_id) {
+            collector.visit_body(context.hir().body(body_id));
+        }
+    }
+
+    collector.expressions
+}
+
+/// Collects the expressions in a body, grouped by the end of their span.
+struct ExpressionCollector<'hir> {
+    expressions: HashMap<BytePos, Vec<&'hir Expr<'hir>>>,
+}
+
+impl<'hir> Visitor<'hir> for ExpressionCollector<'hir> {
+    fn visit_expr(&mut self, expr: &'hir Expr<'hir>) {
+        self.expressions
+            .entry(expr.span.hi())
+            .or_default()
+            .push(expr);
+        walk_expr(self, expr);
+    }
+}
+
+/// Check whether the value of a call is propagated by the function it is made in:
+/// it is (part of) the operand of a try operator, a `return`, or the final expression of the function.
+fn propagates(context: TyCtxt, call_id: HirId) -> bool {
+    let owner = context.hir().enclosing_body_owner(call_id);
+    let Some(body_id) = context.hir().maybe_body_owned_by(owner) else {
+        return false;
+    };
+    let returned =
+        get_returned_expression(context.hir().body(body_id).value).map(|expr| expr.hir_id);
+
+    let node = (call_id, context.hir_node(call_id));
+    for (id, node) in std::iter::once(node).chain(context.hir().parent_iter(call_id)) {
+        if Some(id) == returned {
+            return true;
+        }
+
+        match node {
+            Node::Expr(expr) => match expr.kind {
+                ExprKind::Match(_, _, MatchSource::TryDesugar(_)) | ExprKind::Ret(_) => {
+                    return true;
+                }
+                ExprKind::Closure(_closure) => return false,
+                _ => {}
+            },
+            Node::Item(_) | Node::ImplItem(_) | Node::TraitItem(_) => return false,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// Get the final expression of a function body, of which the value is returned, if any.
+fn get_returned_expression<'a>(body: &'a Expr<'a>) -> Option<&'a Expr<'a>> {
+    let ExprKind::Block(block, _lbl) = body.kind else {
+        // The body of a closure can be a single expression
+        return Some(body);
+    };
+
+    let exp = block.expr?;
+    if let ExprKind::DropTemps(ex) = exp.kind {
+        if let ExprKind::Block(_b, _lbl) = ex.kind {
+            return get_returned_expression(ex);
+        }
+    }
+
+    Some(exp)
+}
@@ -0,0 +1,198 @@
+use crate::analysis::call_index::CallIndex;
+use rustc_hir::def_id::DefId;
+use rustc_hir::HirId;
+use rustc_middle::mir::{
+    AggregateKind, Body, Local, Operand, Place, ProjectionElem, Rvalue, StatementKind,
+    TerminatorKind, RETURN_PLACE,
+};
+use rustc_middle::ty::{Ty, TyCtxt, TyKind};
+use rustc_span::{sym, DesugaringKind};
+use std::collections::HashMap;
+
+/// Methods that consume a `Result` or `Option` without passing its error (or `None`) on.
+const CONSUMING_METHODS: [&str; 13] = [
+    "unwrap",
+    "expect",
+    "unwrap_or",
+    "unwrap_or_else",
+    "unwrap_or_default",
+    "unwrap_unchecked",
+    "ok",
+    "is_ok",
+    "is_err",
+    "is_some",
+    "is_none",
+    "map_or",
+    "map_or_else",
+];
+
+/// Variants of `Result`, `Option` and `ControlFlow` holding the successful value, rather than the error.
+const OK_VARIANTS: [&str; 3] = ["Ok", "Some", "Continue"];
+
+/// Check whether the error returned by a call can reach the return place of the caller,
+/// by following the value through the locals in the MIR of the caller (e.g. `let r = parse(); ...; return r;`).
+/// Returns whether the error was wrapped on the way (e.g. by `Err(e.into())`), or `None` if it can't be returned.
+pub fn get_returned_error<'tcx>(
+    context: TyCtxt<'tcx>,
+    call_index: &mut CallIndex<'tcx>,
+    caller_id: DefId,
+    call_id: HirId,
+) -> Option<bool> {
+    let call = call_index.get(context, caller_id, call_id)?;
+
+    get_returned_local(context, context.optimized_mir(caller_id), call.destination)
+}
+
+/// Check whether the value of a local can reach the return place of a function, by following it through the other locals.
+/// Returns whether it was wrapped on the way, or `None` if it can't be returned.
+pub fn get_returned_local<'tcx>(
+    context: TyCtxt<'tcx>,
+    mir: &Body<'tcx>,
+    local: Local,
+) -> Option<bool> {
+    // The locals holding (part of) the value, and whether it was wrapped to get there
+    let mut tainted: HashMap<Local, bool> = HashMap::new();
+    tainted.insert(local, false);
+
+    // Flow-insensitively taint every local assigned from a tainted local, until nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for block in mir.basic_blocks.iter() {
+            for statement in &block.statements {
+                if let StatementKind::Assign(assign) = &statement.kind {
+                    let (place, rvalue) = &**assign;
+                    if let Some(wrapped) = get_rvalue_taint(context, &tainted, rvalue) {
+                        changed |= taint(&mut tainted, place.local, wrapped);
+                    }
+                }
+            }
+
+            let Some(terminator) = &block.terminator else {
+                continue;
+            };
+            if let TerminatorKind::Call {
+                func,
+                args,
+                destination,
+                fn_span,
+                ..
+            } = &terminator.kind
+            {
+                if let Some(wrapped) = args
+                    .iter()
+                    .filter_map(|arg| get_operand_taint(&tainted, &arg.node))
+                    .min()
+                {
+                    // The calls of the try operator pass the error on as it is, apart from its conversion
+                    let is_try = fn_span.is_desugaring(DesugaringKind::QuestionMark);
+
+                    // Other calls can only pass the error on in a returned `Result` or `Option`
+                    let returns_error =
+                        is_try || is_result_or_option(context, destination.ty(mir, context).ty);
+
+                    if returns_error && passes_on_error(context, func) {
+                        changed |= taint(&mut tainted, destination.local, wrapped || !is_try);
+                    }
+                }
+            }
+        }
+    }
+
+    tainted.get(&RETURN_PLACE).copied()
+}
+
+/// Mark a local as tainted, preferring an unwrapped value over a wrapped one. Returns whether anything changed.
+fn taint(tainted: &mut HashMap<Local, bool>, local: Local, wrapped: bool) -> bool {
+    match tainted.get(&local) {
+        Some(current) if *current <= wrapped => false,
+        _ => {
+            tainted.insert(local, wrapped);
+            true
+        }
+    }
+}
+
+/// Get whether an rvalue reads a tainted local, along with whether it wraps the value.
+fn get_rvalue_taint(
+    context: TyCtxt,
+    tainted: &HashMap<Local, bool>,
+    rvalue: &Rvalue,
+) -> Option<bool> {
+    match rvalue {
+        Rvalue::Use(operand) | Rvalue::Repeat(operand, _) | Rvalue::Cast(_, operand, _) => {
+            get_operand_taint(tainted, operand)
+        }
+        Rvalue::Ref(_, _, place) | Rvalue::AddressOf(_, place) | Rvalue::CopyForDeref(place) => {
+            get_place_taint(tainted, place)
+        }
+        Rvalue::Aggregate(kind, operands) => {
+            let wrapped = operands
+                .iter()
+                .filter_map(|operand| get_operand_taint(tainted, operand))
+                .min()?;
+
+            // Rebuilding a `Result` (e.g. `return Err(e)`) or a tuple does not wrap the error
+            let wraps = match **kind {
+                AggregateKind::Adt(def_id, variant, ..) => {
+                    if context.is_diagnostic_item(sym::Result, def_id)
+                        || context.is_diagnostic_item(sym::Option, def_id)
+                    {
+                        // A value in the `Ok` or `Some` variant (e.g. `Ok(format!("{r:?}"))`) is no error
+                        if context.adt_def(def_id).variant(variant).name != sym::Err {
+                            return None;
+                        }
+                        false
+                    } else {
+                        true
+                    }
+                }
+                AggregateKind::Tuple | AggregateKind::Array(_) => false,
+                _ => true,
+            };
+            Some(wrapped || wraps)
+        }
+        _ => None,
+    }
+}
+
+/// Get whether an operand reads a tainted local, along with whether it was wrapped.
+fn get_operand_taint(tainted: &HashMap<Local, bool>, operand: &Operand) -> Option<bool> {
+    match operand {
+        Operand::Copy(place) | Operand::Move(place) => get_place_taint(tainted, place),
+        Operand::Constant(_) => None,
+    }
+}
+
+/// Get whether a place is (part of) a tainted local, along with whether it was wrapped.
+/// The `Ok` or `Some` value of a tainted `Result` or `Option` does not contain the error.
+fn get_place_taint(tainted: &HashMap<Local, bool>, place: &Place) -> Option<bool> {
+    let wrapped = tainted.get(&place.local)?;
+
+    let is_ok = place.projection.iter().any(|elem| {
+        matches!(elem, ProjectionElem::Downcast(Some(name), _) if OK_VARIANTS.contains(&name.as_str()))
+    });
+
+    (!is_ok).then_some(*wrapped)
+}
+
+/// Check whether a type is a `Result` or `Option`, which can hold an error (or `None`).
+fn is_result_or_option(context: TyCtxt, ty: Ty) -> bool {
+    let TyKind::Adt(adt, _args) = ty.kind() else {
+        return false;
+    };
+
+    context.is_diagnostic_item(sym::Result, adt.did())
+        || context.is_diagnostic_item(sym::Option, adt.did())
+}
+
+/// Check whether a called function may pass an error it is given on in the value it returns,
+/// which is not the case for methods consuming it (e.g. `unwrap` or `is_err`).
+fn passes_on_error(context: TyCtxt, func: &Operand) -> bool {
+    let Some((def_id, _args)) = func.const_fn_def() else {
+        return true;
+    };
+
+    !CONSUMING_METHODS.contains(&context.item_name(def_id).as_str())
+}
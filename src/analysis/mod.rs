@@ -1,57 +1,248 @@
+mod call_index;
 mod calls_to_chains;
+mod combinators;
 mod create_graph;
+mod create_graph_mir;
+mod dataflow;
+mod origins;
+mod panics;
+mod sinks;
 mod types;
 
-use crate::graph::{CallGraph, ChainGraph};
+use crate::graph::{CallGraph, ChainGraph, Location};
+use crate::summary;
+use call_index::CallIndex;
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::LocalDefId;
 use rustc_middle::ty::TyCtxt;
+use rustc_span::sym;
+
+/// The functions from which the call graph is built.
+#[derive(Debug, Clone)]
+pub enum Roots {
+    /// The entry function (e.g. main), or all public functions if the crate has none.
+    Entry,
+    /// Every public function reachable from the crate root.
+    Public,
+    /// Every function annotated with `#[test]`.
+    Tests,
+    /// The functions with the given paths (e.g. `module::function`).
+    Paths(Vec<String>),
+}
+
+/// The way the call graph is built.
+#[derive(Debug, Clone, Copy)]
+pub enum Builder {
+    /// Walk the HIR of each function, resolving the calls using its MIR.
+    Hir,
+    /// Visit the calls in the MIR of each function, including those created by desugaring.
+    Mir,
+}
 
 /// Analysis steps:
 ///
-/// Step 1: Create call graph
+/// Step 1: Create call graph, starting from the selected roots, using the selected builder
 /// Step 1.1: Node for each function
 /// Step 1.2: Edge for each function call
 /// Step 1.3: Add function call information (e.g. whether it propagates using the try op)
+/// Step 1.4: Link the calls closures are passed to with those closures
+/// Step 1.5: Add a node for each place an error is constructed and returned (e.g. `Err(MyError::Parse)`)
 ///
 /// Step 2: Attach return type info to functions in call graph
 /// Step 2.1: Loop over each edge in call graph
 /// Step 2.2: Label edge with type info extracted from MIR
+/// Step 2.3: Annotate edge with the type alias of the returned Result (e.g. `std::io::Result`)
+/// Step 2.4: Annotate edge with the Try type the value is handled through (e.g. `?` on an `Option`)
+/// Step 2.5: Annotate edge with the error conversion performed by the try operator (e.g. through a `From` impl)
+/// Step 2.6: Annotate edge with the combinators the value flows through (e.g. `map_err`), which decide whether it propagates
+/// Step 2.7: Follow the error of non-propagating error edge through the locals of the caller, to see if it is returned anyway
+/// Step 2.8: Annotate non-propagating error edge with what happens to the error (e.g. `unwrap` or `let _ =`)
+/// Step 2.9: Label the edges from calls on trait objects to their possible implementations with the return type of those
 ///
 /// Step 3: Attach panic info to functions in call graph
-/// NOTE: skipped due to lack of time
+/// Step 3.1: Find the panic sources (e.g. `unwrap` or indexing) in the MIR of each local function
+/// Step 3.2: Propagate panics to the callers of panicking functions
 ///
 /// Step 4: Parse the output graph to show individual propagation chains
-pub fn analyze(context: TyCtxt) -> (CallGraph, ChainGraph) {
-    // Get the entry point of the program
-    let entry_node = get_entry_node(context);
+/// NOTE: done separately by `chains`, so the call graphs of several crates can be merged first
+pub fn analyze(context: TyCtxt, roots: &Roots, builder: Builder) -> CallGraph {
+    // Get the functions to start the analysis from
+    let root_nodes = get_root_nodes(context, roots);
+
+    // The calls in the MIR of each function, shared by the graph builder and the type annotation
+    let mut call_index = CallIndex::default();
 
     // Create call graph
-    let mut call_graph =
-        create_graph::create_call_graph_from_root(context, entry_node.expect_item());
+    let mut call_graph = match builder {
+        Builder::Hir => {
+            create_graph::create_call_graph_from_roots(context, &mut call_index, &root_nodes)
+        }
+        Builder::Mir => create_graph_mir::create_call_graph_from_roots(context, &root_nodes),
+    };
+    create_graph::add_closure_calls(context, &mut call_graph);
+    origins::add_error_origins(context, &mut call_graph);
 
     // Attach return type info
     for edge in &mut call_graph.edges {
-        let (ty, error) = types::get_error_or_type(
+        if edge.may_call {
+            let called_id = call_graph.nodes[edge.to].kind.def_id();
+            let ret_ty = types::get_fn_return_type(context, called_id);
+            types::annotate_return_type(context, edge, called_id, ret_ty);
+            continue;
+        }
+
+        let Some(call_id) = edge.call_id else {
+            continue;
+        };
+        let ret_ty = types::get_return_type(
             context,
-            edge.call_id,
+            &mut call_index,
+            call_id,
             call_graph.nodes[edge.from].kind.def_id(),
             call_graph.nodes[edge.to].kind.def_id(),
         );
+        let (ty, error) = types::get_error_or_type(context, ret_ty);
         edge.ty = Some(ty);
-        edge.is_error = error;
+        edge.is_error = error.is_some();
+        edge.error = error;
+        edge.alias = types::get_result_alias(context, call_graph.nodes[edge.to].kind.def_id());
+        edge.try_kind = types::get_try_kind(context, call_id, ret_ty);
+
+        if let Some((conversion, from_impl)) =
+            types::get_error_conversion(context, call_id, call_graph.nodes[edge.from].kind.def_id())
+        {
+            edge.conversion = Some(conversion);
+            edge.from_impl = from_impl.map(|impl_id| summary::get_hash(context, impl_id));
+        }
+
+        // A value that is consumed by a method other than a combinator (e.g. `unwrap`) is not propagated
+        if edge.chain_kind().is_some() {
+            match combinators::get_combinators(context, call_id) {
+                Some(combinators) => edge.combinators = combinators,
+                None => edge.propagates = false,
+            }
+        }
+
+        // An error stored in a local variable can still be returned later on
+        if edge.is_error && !edge.propagates {
+            edge.wrapped = dataflow::get_returned_error(
+                context,
+                &mut call_index,
+                call_graph.nodes[edge.from].kind.def_id(),
+                call_id,
+            );
+            edge.propagates = edge.wrapped.is_some();
+        }
+
+        edge.location = Some(Location::new(context, context.hir().span(call_id)));
+        if edge.is_error && !edge.propagates {
+            edge.sink = Some(sinks::get_error_sink(context, call_id));
+        }
     }
 
-    // Parse graph to show chains
-    let chain_graph = calls_to_chains::to_chains(&call_graph);
+    // Attach panic info
+    panics::attach_panics(context, &mut call_graph);
+
+    call_graph
+}
+
+/// Parse the call graph to show individual propagation chains.
+pub fn chains(call_graph: &CallGraph) -> ChainGraph {
+    calls_to_chains::to_chains(call_graph)
+}
 
-    (call_graph, chain_graph)
+/// Propagate panics to the callers of panicking functions, e.g. across the crates of a merged call graph.
+pub fn propagate_panics(call_graph: &mut CallGraph) {
+    panics::propagate_panics(call_graph);
+}
+
+/// Retrieve the root functions for the given root selection from the type context.
+fn get_root_nodes(context: TyCtxt, roots: &Roots) -> Vec<LocalDefId> {
+    match roots {
+        Roots::Entry => {
+            if let Some(def_id) = get_entry_node(context) {
+                vec![def_id]
+            } else {
+                println!("Could not find entry function, using public functions instead!");
+                get_public_nodes(context)
+            }
+        }
+        Roots::Public => get_public_nodes(context),
+        Roots::Tests => get_test_nodes(context),
+        Roots::Paths(paths) => {
+            let nodes = get_path_nodes(context, paths);
+            if nodes.len() < paths.len() {
+                eprintln!("Could not find all provided root functions!");
+            }
+            nodes
+        }
+    }
 }
 
 /// Retrieve the entry node (aka main function) from the type context.
-fn get_entry_node(context: TyCtxt) -> rustc_hir::Node {
-    let (def_id, _entry_type) = context
-        .entry_fn(())
-        .expect("Could not find entry function!");
-    let id = context
-        .local_def_id_to_hir_id(def_id.as_local().expect("Entry function def id not local!"));
-    context.hir_node(id)
+fn get_entry_node(context: TyCtxt) -> Option<LocalDefId> {
+    let (def_id, _entry_type) = context.entry_fn(())?;
+    def_id.as_local()
+}
+
+/// Retrieve all public functions that are reachable from the crate root.
+fn get_public_nodes(context: TyCtxt) -> Vec<LocalDefId> {
+    let visibilities = context.effective_visibilities(());
+
+    get_function_nodes(context)
+        .filter(|def_id| visibilities.is_exported(*def_id))
+        .collect()
+}
+
+/// Retrieve all functions annotated with `#[test]`.
+fn get_test_nodes(context: TyCtxt) -> Vec<LocalDefId> {
+    // The test harness generates a const with the same name next to each test function, marked with `#[rustc_test_marker]`
+    let markers: Vec<LocalDefId> = context
+        .hir_crate_items(())
+        .definitions()
+        .filter(|def_id| context.def_kind(*def_id) == DefKind::Const)
+        .filter(|def_id| {
+            context
+                .hir()
+                .attrs(context.local_def_id_to_hir_id(*def_id))
+                .iter()
+                .any(|attr| attr.has_name(sym::rustc_test_marker))
+        })
+        .collect();
+
+    get_function_nodes(context)
+        .filter(|def_id| {
+            markers.iter().any(|marker| {
+                context.item_name(marker.to_def_id()) == context.item_name(def_id.to_def_id())
+                    && context.parent_module_from_def_id(*marker)
+                        == context.parent_module_from_def_id(*def_id)
+            })
+        })
+        .collect()
+}
+
+/// Retrieve the functions with the given paths.
+fn get_path_nodes(context: TyCtxt, paths: &[String]) -> Vec<LocalDefId> {
+    get_function_nodes(context)
+        .filter(|def_id| {
+            let path = context.def_path_str(def_id.to_def_id());
+            paths
+                .iter()
+                .any(|p| p.trim_start_matches("crate::") == path.trim_start_matches("crate::"))
+        })
+        .collect()
+}
+
+/// Retrieve all local functions (and methods) that have a body.
+fn get_function_nodes(context: TyCtxt<'_>) -> impl Iterator<Item = LocalDefId> + '_ {
+    context
+        .hir_crate_items(())
+        .definitions()
+        .filter(move |def_id| matches!(context.def_kind(*def_id), DefKind::Fn | DefKind::AssocFn))
+        .filter(move |def_id| {
+            matches!(
+                context.hir_node(context.local_def_id_to_hir_id(*def_id)),
+                rustc_hir::Node::Item(_) | rustc_hir::Node::ImplItem(_)
+            )
+        })
 }
@@ -0,0 +1,212 @@
+use crate::analysis::dataflow;
+use crate::graph::{CallEdge, CallGraph, CallNodeKind, ErrorType, Location, TryKind};
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir::{
+    AggregateKind, Body, Local, Operand, Place, ProjectionElem, Rvalue, StatementKind,
+    TerminatorKind,
+};
+use rustc_middle::ty::{Ty, TyCtxt, TyKind};
+use rustc_span::{sym, ExpnKind, Span};
+
+/// Macros of error handling crates (e.g. anyhow and eyre) that construct an error.
+const ORIGIN_MACROS: [&str; 5] = ["anyhow", "bail", "ensure", "eyre", "format_err"];
+
+/// The construction of an error that is returned by the function it is constructed in.
+struct ErrorOrigin<'tcx> {
+    /// What constructs the error, e.g. `MyError::Parse`, `std::io::Error::new` or `bail!`.
+    label: String,
+    error: Ty<'tcx>,
+    span: Span,
+}
+
+/// Add a node for each place an error is constructed in a local function and then returned,
+/// with a propagating edge from the function to it, so chains start where the error is born.
+pub fn add_error_origins(context: TyCtxt, graph: &mut CallGraph) {
+    let functions: Vec<(usize, DefId)> = graph
+        .nodes
+        .iter()
+        .filter_map(|node| match node.kind {
+            CallNodeKind::LocalFn(def_id, _hir_id) => Some((node.id(), def_id)),
+            _ => None,
+        })
+        .collect();
+
+    for (from, def_id) in functions {
+        if !context.is_mir_available(def_id) {
+            continue;
+        }
+
+        let origins = get_error_origins(context, context.optimized_mir(def_id));
+        for (index, origin) in origins.into_iter().enumerate() {
+            let location = Location::new(context, origin.span);
+
+            let id = graph.add_node(&origin.label, CallNodeKind::error_origin(def_id, index));
+            let node = &mut graph.nodes[id];
+            node.krate = context.crate_name(def_id.krate).to_string();
+            node.location = Some(location.clone());
+
+            let mut edge = CallEdge::new(from, id, None, true);
+            edge.ty = Some(format!("{}", origin.error));
+            edge.is_error = true;
+            edge.error = Some(ErrorType::new(context, origin.error));
+            edge.try_kind = Some(TryKind::Result);
+            edge.location = Some(location);
+            graph.add_edge(edge);
+        }
+    }
+}
+
+/// Find the places in the MIR of a function where an `Err` is constructed from a new error and returned.
+fn get_error_origins<'tcx>(context: TyCtxt<'tcx>, mir: &Body<'tcx>) -> Vec<ErrorOrigin<'tcx>> {
+    let mut res = vec![];
+
+    for block in mir.basic_blocks.iter() {
+        for statement in &block.statements {
+            let StatementKind::Assign(assign) = &statement.kind else {
+                continue;
+            };
+            let (place, Rvalue::Aggregate(kind, operands)) = &**assign else {
+                continue;
+            };
+            let AggregateKind::Adt(def_id, variant, args, _, _) = **kind else {
+                continue;
+            };
+            if !context.is_diagnostic_item(sym::Result, def_id)
+                || context.adt_def(def_id).variant(variant).name != sym::Err
+            {
+                continue;
+            }
+
+            // Errors that are taken from another `Result` (e.g. `return Err(e.into())`) are not new
+            let Some(source) = operands
+                .iter()
+                .next()
+                .and_then(|operand| get_error_source(context, mir, operand, &mut vec![]))
+            else {
+                continue;
+            };
+
+            // Errors that are handled within the function don't start a chain
+            if dataflow::get_returned_local(context, mir, place.local).is_none() {
+                continue;
+            }
+
+            let span = statement.source_info.span;
+            res.push(ErrorOrigin {
+                label: get_origin_macro(span).unwrap_or(source),
+                error: args.type_at(1),
+                span,
+            });
+        }
+    }
+
+    res
+}
+
+/// Get what constructs an error value, e.g. `MyError::Parse` for an enum variant or `std::io::Error::new` for a call,
+/// or `None` if it is (converted from) an existing error that was taken from a `Result`.
+fn get_error_source<'tcx>(
+    context: TyCtxt<'tcx>,
+    mir: &Body<'tcx>,
+    operand: &Operand<'tcx>,
+    visited: &mut Vec<Local>,
+) -> Option<String> {
+    let place = match operand {
+        Operand::Copy(place) | Operand::Move(place) => place,
+        Operand::Constant(constant) => return Some(format!("{}", constant.ty())),
+    };
+
+    if is_result_error(context, mir, place) {
+        return None;
+    }
+    if visited.contains(&place.local) {
+        return Some(format!("{}", mir.local_decls[place.local].ty));
+    }
+    visited.push(place.local);
+
+    for block in mir.basic_blocks.iter() {
+        for statement in &block.statements {
+            let StatementKind::Assign(assign) = &statement.kind else {
+                continue;
+            };
+            let (assigned, rvalue) = &**assign;
+            if assigned.local != place.local {
+                continue;
+            }
+
+            match rvalue {
+                Rvalue::Aggregate(kind, operands) => {
+                    for operand in operands {
+                        get_error_source(context, mir, operand, visited)?;
+                    }
+
+                    if let AggregateKind::Adt(def_id, variant, _, _, _) = **kind {
+                        let adt = context.adt_def(def_id);
+                        return Some(if adt.is_enum() {
+                            context.def_path_str(adt.variant(variant).def_id)
+                        } else {
+                            context.def_path_str(def_id)
+                        });
+                    }
+                }
+                Rvalue::Use(operand) | Rvalue::Cast(_, operand, _) => {
+                    return get_error_source(context, mir, operand, visited);
+                }
+                Rvalue::Ref(_, _, place) | Rvalue::CopyForDeref(place) => {
+                    return get_error_source(context, mir, &Operand::Copy(*place), visited);
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(terminator) = &block.terminator {
+            if let TerminatorKind::Call {
+                func,
+                args,
+                destination,
+                ..
+            } = &terminator.kind
+            {
+                if destination.local != place.local {
+                    continue;
+                }
+
+                // Converting an existing error (e.g. `From::from(e)`) does not construct a new one
+                for arg in args.iter() {
+                    get_error_source(context, mir, &arg.node, visited)?;
+                }
+
+                if let Some((def_id, _args)) = func.const_fn_def() {
+                    return Some(context.def_path_str(def_id));
+                }
+            }
+        }
+    }
+
+    Some(format!("{}", mir.local_decls[place.local].ty))
+}
+
+/// Check whether a place is taken out of the `Err` variant of a `Result`, i.e. it is an existing error.
+/// Payloads of other variants (e.g. the name in `Some(name)`) can still be used to construct a new error.
+fn is_result_error<'tcx>(context: TyCtxt<'tcx>, mir: &Body<'tcx>, place: &Place<'tcx>) -> bool {
+    place.iter_projections().any(|(base, elem)| {
+        let ProjectionElem::Downcast(_name, variant) = elem else {
+            return false;
+        };
+        let TyKind::Adt(adt, _args) = base.ty(mir, context).ty.kind() else {
+            return false;
+        };
+
+        context.is_diagnostic_item(sym::Result, adt.did()) && adt.variant(variant).name == sym::Err
+    })
+}
+
+/// Get the name of the error handling macro (e.g. `bail!`) the span was expanded from, if any.
+fn get_origin_macro(span: Span) -> Option<String> {
+    span.macro_backtrace().find_map(|expn| match expn.kind {
+        ExpnKind::Macro(_kind, name) if ORIGIN_MACROS.contains(&name.as_str()) => {
+            Some(format!("{name}!"))
+        }
+        _ => None,
+    })
+}
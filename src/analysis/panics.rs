@@ -0,0 +1,168 @@
+use crate::graph::{CallGraph, CallNode, CallNodeKind, PanicSource};
+use rustc_hir::def_id::DefId;
+use rustc_hir::LangItem;
+use rustc_middle::mir::{AssertKind, AssertMessage, TerminatorKind};
+use rustc_middle::ty::{TyCtxt, TyKind};
+use rustc_span::{sym, ExpnKind, Span};
+
+/// Attach panic info to the local functions in the call graph, and propagate it to their callers.
+pub fn attach_panics(context: TyCtxt, graph: &mut CallGraph) {
+    // Find the panic sources within each local function
+    for node in &mut graph.nodes {
+        if let CallNodeKind::LocalFn(def_id, _hir_id) = node.kind {
+            node.panic_sources = get_panic_sources(context, def_id);
+            node.panics = !node.panic_sources.is_empty();
+        }
+    }
+
+    propagate_panics(graph);
+}
+
+/// Propagate panics to the callers of panicking functions, visiting the callers of each function that starts panicking.
+pub fn propagate_panics(graph: &mut CallGraph) {
+    let mut panicking: Vec<usize> = graph
+        .nodes
+        .iter()
+        .filter(|node| node.panics)
+        .map(CallNode::id)
+        .collect();
+    while let Some(callee) = panicking.pop() {
+        let source = PanicSource::Call(graph.nodes[callee].label.clone());
+        let callers: Vec<usize> = graph
+            .get_incoming_edges(callee)
+            .iter()
+            .map(|edge| edge.from)
+            .collect();
+
+        for caller in callers {
+            let caller_node = &mut graph.nodes[caller];
+            if !caller_node.panic_sources.contains(&source) {
+                caller_node.panic_sources.push(source.clone());
+            }
+            if !caller_node.panics {
+                caller_node.panics = true;
+                panicking.push(caller);
+            }
+        }
+    }
+}
+
+/// Get the reasons why a function can panic from its MIR, without looking at the functions it calls.
+fn get_panic_sources(context: TyCtxt, def_id: DefId) -> Vec<PanicSource> {
+    let mut res = vec![];
+
+    if !context.is_mir_available(def_id) {
+        return res;
+    }
+
+    let mir = context.optimized_mir(def_id);
+
+    for block in mir.basic_blocks.iter() {
+        if let Some(terminator) = &block.terminator {
+            let source = match &terminator.kind {
+                TerminatorKind::Assert { msg, .. } => Some(get_assert_source(msg)),
+                TerminatorKind::Call { func, .. } => {
+                    func.const_fn_def().and_then(|(called_id, _args)| {
+                        get_call_source(context, called_id, terminator.source_info.span)
+                    })
+                }
+                _ => None,
+            };
+
+            if let Some(source) = source {
+                if !res.contains(&source) {
+                    res.push(source);
+                }
+            }
+        }
+    }
+
+    res
+}
+
+/// Get the panic source of an `Assert` terminator, which are inserted for bounds and overflow checks.
+fn get_assert_source(msg: &AssertMessage) -> PanicSource {
+    match msg {
+        AssertKind::BoundsCheck { .. } => PanicSource::Index,
+        AssertKind::Overflow(..)
+        | AssertKind::OverflowNeg(_)
+        | AssertKind::DivisionByZero(_)
+        | AssertKind::RemainderByZero(_) => PanicSource::Overflow,
+        _ => PanicSource::Assert,
+    }
+}
+
+/// Get the panic source of a call, or `None` if the called function itself doesn't panic.
+fn get_call_source(context: TyCtxt, called_id: DefId, span: Span) -> Option<PanicSource> {
+    let path = context.def_path_str(called_id);
+
+    // Functions used by the panicking macros
+    let is_panic_item = context.lang_items().iter().any(|(item, def_id)| {
+        def_id == called_id
+            && matches!(
+                item,
+                LangItem::Panic
+                    | LangItem::PanicNounwind
+                    | LangItem::PanicFmt
+                    | LangItem::ConstPanicFmt
+                    | LangItem::BeginPanic
+            )
+    });
+    if is_panic_item
+        || path.starts_with("core::panicking::")
+        || path.starts_with("std::panicking::")
+        || path.starts_with("std::rt::begin_panic")
+    {
+        return Some(get_macro_source(span));
+    }
+
+    // The failure paths of `unwrap` and `expect`, in case they were inlined
+    if path == "core::option::unwrap_failed" || path == "core::result::unwrap_failed" {
+        return Some(PanicSource::Unwrap);
+    }
+    if path == "core::option::expect_failed" {
+        return Some(PanicSource::Expect);
+    }
+
+    // Indexing that is not built-in, e.g. on a `Vec` or using a range
+    if let Some(trait_id) = context.trait_of_item(called_id) {
+        let lang_items = context.lang_items();
+        if Some(trait_id) == lang_items.index_trait()
+            || Some(trait_id) == lang_items.index_mut_trait()
+        {
+            return Some(PanicSource::Index);
+        }
+    }
+
+    // Calls to `unwrap` and `expect` on `Option` and `Result`
+    let impl_id = context.impl_of_method(called_id)?;
+    if let TyKind::Adt(adt, _args) = context.type_of(impl_id).instantiate_identity().kind() {
+        if context.is_diagnostic_item(sym::Option, adt.did())
+            || context.is_diagnostic_item(sym::Result, adt.did())
+        {
+            return match context.opt_item_name(called_id)?.as_str() {
+                "unwrap" | "unwrap_err" => Some(PanicSource::Unwrap),
+                "expect" | "expect_err" => Some(PanicSource::Expect),
+                _ => None,
+            };
+        }
+    }
+
+    None
+}
+
+/// Get the panic source of an explicit panic, based on the macro it was expanded from.
+fn get_macro_source(span: Span) -> PanicSource {
+    if let Some(expn) = span.source_callee() {
+        if let ExpnKind::Macro(_kind, name) = expn.kind {
+            match name.as_str() {
+                "unreachable" => return PanicSource::Unreachable,
+                "assert" | "assert_eq" | "assert_ne" | "debug_assert" | "debug_assert_eq"
+                | "debug_assert_ne" => return PanicSource::Assert,
+                _ => {}
+            }
+        }
+    }
+
+    PanicSource::Panic
+}
@@ -0,0 +1,81 @@
+use crate::graph::ErrorSink;
+use rustc_hir::{Expr, ExprKind, HirId, MatchSource, Node, PatKind, StmtKind};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::{DesugaringKind, ExpnKind, Span};
+
+/// Macros that print or log their arguments.
+const LOGGING_MACROS: [&str; 11] = [
+    "print", "println", "eprint", "eprintln", "dbg", "log", "trace", "debug", "info", "warn",
+    "error",
+];
+
+/// Get what happens to the value returned by a call, when it is not propagated.
+pub fn get_error_sink(context: TyCtxt, call_id: HirId) -> ErrorSink {
+    let mut child_id = call_id;
+
+    for (parent_id, node) in context.hir().parent_iter(call_id) {
+        match node {
+            Node::Expr(expr) => {
+                // Look through `.await` and temporaries, as the value is still the same
+                if expr.span.is_desugaring(DesugaringKind::Await)
+                    || matches!(expr.kind, ExprKind::DropTemps(_))
+                {
+                    child_id = parent_id;
+                    continue;
+                }
+
+                return get_expression_sink(expr, child_id);
+            }
+            Node::LetStmt(stmt) => {
+                return match stmt.pat.kind {
+                    PatKind::Wild => ErrorSink::LetUnderscore,
+                    PatKind::Binding(_mode, _hir_id, ident, None)
+                        if ident.as_str().starts_with('_') =>
+                    {
+                        ErrorSink::LetUnderscore
+                    }
+                    _ => ErrorSink::Other,
+                };
+            }
+            Node::Stmt(stmt) => {
+                return if matches!(stmt.kind, StmtKind::Semi(_)) {
+                    ErrorSink::Discard
+                } else {
+                    ErrorSink::Other
+                };
+            }
+            _ => return ErrorSink::Other,
+        }
+    }
+
+    ErrorSink::Other
+}
+
+/// Get what happens to the value of the child expression within its parent expression.
+fn get_expression_sink(expr: &Expr, child_id: HirId) -> ErrorSink {
+    match expr.kind {
+        ExprKind::MethodCall(segment, receiver, _args, _span) if receiver.hir_id == child_id => {
+            match segment.ident.as_str() {
+                "unwrap" | "unwrap_err" | "unwrap_unchecked" => ErrorSink::Unwrap,
+                "expect" | "expect_err" => ErrorSink::Expect,
+                name if name.starts_with("unwrap_or") => ErrorSink::UnwrapOr,
+                "ok" | "err" => ErrorSink::Ok,
+                "map_err" => ErrorSink::MapErr,
+                _ => ErrorSink::Other,
+            }
+        }
+        ExprKind::Match(scrutinee, _arms, MatchSource::Normal) if scrutinee.hir_id == child_id => {
+            ErrorSink::Handled
+        }
+        ExprKind::Let(let_expr) if let_expr.init.hir_id == child_id => ErrorSink::Handled,
+        _ if is_logging(expr.span) => ErrorSink::Logged,
+        _ => ErrorSink::Other,
+    }
+}
+
+/// Check whether an expression is part of a printing or logging macro.
+fn is_logging(span: Span) -> bool {
+    span.source_callee().is_some_and(|expn| {
+        matches!(expn.kind, ExpnKind::Macro(_kind, name) if LOGGING_MACROS.contains(&name.as_str()))
+    })
+}
@@ -1,13 +1,23 @@
+use crate::analysis::call_index::CallIndex;
+use crate::graph::{CallEdge, ErrorConversion, ErrorType, TryKind};
+use rustc_hir::def::{DefKind, Res};
 use rustc_hir::def_id::DefId;
-use rustc_hir::HirId;
-use rustc_middle::mir::TerminatorKind;
-use rustc_middle::ty::{GenericArg, Interner, Ty, TyCtxt, TyKind};
+use rustc_hir::{ExprKind, FnRetTy, HirId, LangItem, MatchSource, Node, QPath};
+use rustc_middle::ty::print::PrintTraitRefExt;
+use rustc_middle::ty::{GenericArg, Instance, Ty, TyCtxt, TyKind, TypeVisitableExt};
+use rustc_span::sym;
 
 /// Get the return type of a called function.
 #[allow(clippy::similar_names)]
-fn get_call_type(context: TyCtxt, call_id: HirId, caller_id: DefId, called_id: DefId) -> Ty {
-    if let Some(ty) = get_call_type_using_mir(context, call_id, caller_id) {
-        ty
+fn get_call_type<'tcx>(
+    context: TyCtxt<'tcx>,
+    calls: &mut CallIndex<'tcx>,
+    call_id: HirId,
+    caller_id: DefId,
+    called_id: DefId,
+) -> Ty<'tcx> {
+    if let Some(call) = calls.get(context, caller_id, call_id) {
+        call.ret_ty
     } else {
         get_call_type_using_context(context, called_id)
     }
@@ -27,81 +37,351 @@ fn get_call_type_using_context(context: TyCtxt, called_id: DefId) -> Ty {
     }
 }
 
-/// Extracts the return type of a called function using its call's `HirId`, as well as the caller's `DefId`.
-/// Returns `None` if no MIR is available or the call was not found (e.g. due to desugaring/optimizations).
-fn get_call_type_using_mir(context: TyCtxt, call_id: HirId, caller_id: DefId) -> Option<Ty> {
-    if !context.is_mir_available(caller_id) {
-        return None;
-    }
-
-    let mir = context.optimized_mir(caller_id);
-    let call_expr = context.hir_node(call_id).expect_expr();
-
-    for block in mir.basic_blocks.iter() {
-        if let Some(terminator) = &block.terminator {
-            if let TerminatorKind::Call { func, fn_span, .. } = &terminator.kind {
-                if call_expr.span.hi() == fn_span.hi() {
-                    if let Some((def_id, args)) = func.const_fn_def() {
-                        return Some(
-                            context
-                                .type_of_instantiated(def_id, args)
-                                .fn_sig(context)
-                                .output()
-                                .skip_binder(),
-                        );
-                    }
-                }
-            }
-        }
+/// Get the declared return type of a function or closure, with its projections normalized.
+pub fn get_fn_return_type(context: TyCtxt, fn_id: DefId) -> Ty {
+    let ty = context.type_of(fn_id).instantiate_identity();
+    if let TyKind::Closure(_def_id, args) = ty.kind() {
+        return normalize(
+            context,
+            fn_id,
+            args.as_closure().sig().output().skip_binder(),
+        );
     }
 
-    None
+    normalize(context, fn_id, get_call_type_using_context(context, fn_id))
 }
 
-/// Extract the error type from Result, or return the full type if it doesn't contain a Result (along with a flag of whether it is an extract error).
+/// Get the return type of a called function, with its projections normalized.
 #[allow(clippy::similar_names)]
-pub fn get_error_or_type(
-    context: TyCtxt,
+pub fn get_return_type<'tcx>(
+    context: TyCtxt<'tcx>,
+    calls: &mut CallIndex<'tcx>,
     call_id: HirId,
     caller_id: DefId,
     called_id: DefId,
-) -> (String, bool) {
-    let ret_ty = get_call_type(context, call_id, caller_id, called_id);
+) -> Ty<'tcx> {
+    normalize(
+        context,
+        caller_id,
+        get_call_type(context, calls, call_id, caller_id, called_id),
+    )
+}
 
+/// Extract the error type from Result, or return the full type if it doesn't contain a Result (along with the error type, if any).
+pub fn get_error_or_type<'a>(context: TyCtxt<'a>, ret_ty: Ty<'a>) -> (String, Option<ErrorType>) {
     let result = if context.ty_is_opaque_future(ret_ty) {
         extract_result_from_future(context, ret_ty)
     } else {
-        extract_result(ret_ty)
+        extract_result(context, ret_ty)
     };
 
-    let res = extract_error_from_result(result);
+    match result.and_then(extract_error_from_result) {
+        Some(error) => (format!("{error}"), Some(ErrorType::new(context, error))),
+        None => (format!("{ret_ty}"), None),
+    }
+}
 
-    (res.clone().unwrap_or(format!("{ret_ty}")), res.is_some())
+/// Annotate an edge with the type returned by the called function, along with its error, alias and Try type.
+/// Used for edges that have no call expression to get the Try type or error conversion from.
+pub fn annotate_return_type<'a>(
+    context: TyCtxt<'a>,
+    edge: &mut CallEdge,
+    called_id: DefId,
+    ret_ty: Ty<'a>,
+) {
+    let (ty, error) = get_error_or_type(context, ret_ty);
+    edge.ty = Some(ty);
+    edge.is_error = error.is_some();
+    edge.error = error;
+    edge.alias = get_result_alias(context, called_id);
+    edge.try_kind = get_return_try_kind(context, ret_ty);
 }
 
-/// Extract the Result type from any type.
-fn extract_result(ty: Ty) -> Option<GenericArg> {
-    for arg in ty.walk() {
-        let format = format!("{arg}");
-        if format.starts_with("std::result::Result<") && format.ends_with('>') {
-            return Some(arg);
+/// Get the kind of Try type through which the value of a call is handled.
+/// This is the type the try operator was applied to if the call is (part of) its operand, and the return type otherwise.
+pub fn get_try_kind<'a>(context: TyCtxt<'a>, call_id: HirId, ret_ty: Ty<'a>) -> Option<TryKind> {
+    if let Some(operand_ty) = get_try_operand_type(context, call_id) {
+        // Anything the try operator is applied to implements `Try`
+        return Some(
+            classify_try_type(context, operand_ty)
+                .unwrap_or_else(|| TryKind::Other(format!("{operand_ty}"))),
+        );
+    }
+
+    get_return_try_kind(context, ret_ty)
+}
+
+/// Get the kind of Try type a function returns, looking through futures.
+pub fn get_return_try_kind<'a>(context: TyCtxt<'a>, ret_ty: Ty<'a>) -> Option<TryKind> {
+    if context.ty_is_opaque_future(ret_ty) {
+        classify_try_type(context, get_future_output(context, ret_ty)?)
+    } else {
+        classify_try_type(context, ret_ty)
+    }
+}
+
+/// Get the conversion of the error by the try operator that encloses a call, if it converts a Result,
+/// along with the `From` impl it is converted through (if the error types differ).
+#[allow(clippy::similar_names)]
+pub fn get_error_conversion(
+    context: TyCtxt,
+    call_id: HirId,
+    caller_id: DefId,
+) -> Option<(ErrorConversion, Option<DefId>)> {
+    // The error that is propagated by the try operator
+    let operand_ty = get_try_operand_type(context, call_id)?;
+    if !is_result(context, operand_ty) {
+        return None;
+    }
+    let source = context.erase_regions(extract_error_from_result(operand_ty)?);
+
+    // The error that is returned by the caller
+    if !matches!(context.def_kind(caller_id), DefKind::Fn | DefKind::AssocFn) {
+        return None;
+    }
+    let ret_ty = context
+        .fn_sig(caller_id)
+        .instantiate_identity()
+        .output()
+        .skip_binder();
+    let ret_ty = if context.ty_is_opaque_future(ret_ty) {
+        get_future_output(context, ret_ty)?
+    } else {
+        ret_ty
+    };
+    if !is_result(context, ret_ty) {
+        return None;
+    }
+    let target = context.erase_regions(extract_error_from_result(ret_ty)?);
+
+    // Errors of the same type are converted by the blanket `impl<T> From<T> for T`, which is left out
+    let from_impl = if source == target {
+        None
+    } else {
+        resolve_from_impl(context, caller_id, source, target)
+    };
+
+    let conversion = ErrorConversion {
+        source: ErrorType::new(context, source),
+        target: ErrorType::new(context, target),
+        via: from_impl.map(|impl_id| get_impl_name(context, impl_id)),
+    };
+
+    Some((conversion, from_impl))
+}
+
+/// Resolve the `From` impl that converts the source type into the target type.
+fn resolve_from_impl<'a>(
+    context: TyCtxt<'a>,
+    caller_id: DefId,
+    source: Ty<'a>,
+    target: Ty<'a>,
+) -> Option<DefId> {
+    if source.has_escaping_bound_vars() || target.has_escaping_bound_vars() {
+        return None;
+    }
+
+    let from_trait = context.get_diagnostic_item(sym::From)?;
+    let from_fn = *context.associated_item_def_ids(from_trait).first()?;
+    let args = context.mk_args(&[target.into(), source.into()]);
+
+    let instance =
+        Instance::resolve(context, context.param_env(caller_id), from_fn, args).ok()??;
+
+    context.impl_of_method(instance.def_id())
+}
+
+/// Get the name of a trait impl, e.g. `impl From<ReadError> for AppError`.
+fn get_impl_name(context: TyCtxt, impl_id: DefId) -> String {
+    let self_ty = context.type_of(impl_id).instantiate_identity();
+
+    match context.impl_trait_ref(impl_id) {
+        Some(trait_ref) => format!(
+            "impl {} for {self_ty}",
+            trait_ref.instantiate_identity().print_only_trait_path()
+        ),
+        None => format!("impl {self_ty}"),
+    }
+}
+
+/// Get the type of the operand of the try operator that encloses a call, if any.
+fn get_try_operand_type(context: TyCtxt, call_id: HirId) -> Option<Ty> {
+    for (_id, node) in context.hir().parent_iter(call_id) {
+        match node {
+            Node::Expr(expr) => match expr.kind {
+                // The operand is desugared to `Try::branch(operand)`
+                ExprKind::Match(scrutinee, _arms, MatchSource::TryDesugar(_)) => {
+                    if let ExprKind::Call(_branch, [operand]) = scrutinee.kind {
+                        return Some(context.typeck(call_id.owner.def_id).expr_ty(operand));
+                    }
+                    return None;
+                }
+                // The try operator within a closure doesn't propagate out of it
+                ExprKind::Closure(_closure) => return None,
+                _ => {}
+            },
+            Node::Item(_) | Node::ImplItem(_) | Node::TraitItem(_) => return None,
+            _ => {}
         }
     }
 
     None
 }
 
+/// Get the kind of a well-known Try type, or `None` if it is not one.
+fn classify_try_type(context: TyCtxt, ty: Ty) -> Option<TryKind> {
+    let TyKind::Adt(adt, _args) = ty.kind() else {
+        return None;
+    };
+    let def_id = adt.did();
+
+    if context.is_diagnostic_item(sym::Result, def_id) {
+        Some(TryKind::Result)
+    } else if context.is_diagnostic_item(sym::Option, def_id) {
+        Some(TryKind::Option)
+    } else if context.lang_items().get(LangItem::Poll) == Some(def_id) {
+        Some(TryKind::Poll)
+    } else if context
+        .lang_items()
+        .get(LangItem::ControlFlowContinue)
+        .is_some_and(|variant| context.parent(variant) == def_id)
+    {
+        // `ControlFlow` itself is no lang or diagnostic item, but its variants are lang items
+        Some(TryKind::ControlFlow)
+    } else {
+        None
+    }
+}
+
+/// Get the type alias of the Result a function returns (e.g. `std::io::Result` or `anyhow::Result`), if any.
+/// Aliases are already expanded in the types themselves, so this looks at the declared return type instead.
+pub fn get_result_alias(context: TyCtxt, called_id: DefId) -> Option<String> {
+    let Some(local_id) = called_id.as_local() else {
+        return get_extern_result_alias(context, called_id);
+    };
+    let decl = context
+        .hir_node(context.local_def_id_to_hir_id(local_id))
+        .fn_decl()?;
+
+    let FnRetTy::Return(ty) = decl.output else {
+        return None;
+    };
+    let rustc_hir::TyKind::Path(QPath::Resolved(_ty, path)) = ty.kind else {
+        return None;
+    };
+    let Res::Def(DefKind::TyAlias, alias_id) = path.res else {
+        return None;
+    };
+
+    is_result(context, context.type_of(alias_id).instantiate_identity())
+        .then(|| context.def_path_str(alias_id))
+}
+
+/// Get the type alias of the Result a function of another crate returns, if any.
+/// The metadata only contains the expanded signature, so this looks for a Result alias with the same error type
+/// next to the function or the error type (e.g. `std::io::Result` next to `std::io::Error`).
+fn get_extern_result_alias(context: TyCtxt, called_id: DefId) -> Option<String> {
+    if !matches!(context.def_kind(called_id), DefKind::Fn | DefKind::AssocFn) {
+        return None;
+    }
+
+    let ret_ty = context
+        .fn_sig(called_id)
+        .instantiate_identity()
+        .output()
+        .skip_binder();
+    let TyKind::Adt(adt, args) = ret_ty.kind() else {
+        return None;
+    };
+    if !context.is_diagnostic_item(sym::Result, adt.did()) {
+        return None;
+    }
+    let error = args.type_at(1);
+
+    let mut modules = vec![get_module(context, called_id)];
+    if let TyKind::Adt(error_adt, _args) = error.kind() {
+        modules.push(get_module(context, error_adt.did()));
+    }
+
+    modules
+        .into_iter()
+        .flat_map(|module| context.module_children(module))
+        .find_map(|child| {
+            let Res::Def(DefKind::TyAlias, alias_id) = child.res else {
+                return None;
+            };
+            let alias_ty = context.type_of(alias_id).instantiate_identity();
+            let TyKind::Adt(alias_adt, alias_args) = alias_ty.kind() else {
+                return None;
+            };
+
+            (context.is_diagnostic_item(sym::Result, alias_adt.did())
+                && get_alias_error(context, alias_id, alias_args.type_at(1)) == Some(error))
+            .then(|| context.def_path_str(alias_id))
+        })
+}
+
+/// Get the error type of a Result alias, which is the default of its parameter if the error is generic
+/// (e.g. `Error` in `anyhow::Result<T, E = Error>`), or `None` if that parameter has no default.
+fn get_alias_error<'a>(context: TyCtxt<'a>, alias_id: DefId, error: Ty<'a>) -> Option<Ty<'a>> {
+    let TyKind::Param(param) = error.kind() else {
+        return Some(error);
+    };
+
+    context
+        .generics_of(alias_id)
+        .type_param(*param, context)
+        .default_value(context)?
+        .instantiate_identity()
+        .as_type()
+}
+
+/// Get the module an item (e.g. a function or method) is defined in.
+fn get_module(context: TyCtxt, def_id: DefId) -> DefId {
+    let mut module = context.parent(def_id);
+    while context.def_kind(module) != DefKind::Mod {
+        module = context.parent(module);
+    }
+    module
+}
+
+/// Normalize the projections (e.g. associated types) in a type, leaving it unchanged if that is not possible.
+fn normalize<'a>(context: TyCtxt<'a>, caller_id: DefId, ty: Ty<'a>) -> Ty<'a> {
+    if ty.has_escaping_bound_vars() {
+        return ty;
+    }
+
+    context
+        .try_normalize_erasing_regions(context.param_env(caller_id), ty)
+        .unwrap_or(ty)
+}
+
+/// Check whether a type is a Result.
+fn is_result(context: TyCtxt, ty: Ty) -> bool {
+    if let TyKind::Adt(adt, _args) = ty.kind() {
+        context.is_diagnostic_item(sym::Result, adt.did())
+    } else {
+        false
+    }
+}
+
+/// Extract the Result type from any type.
+fn extract_result<'a>(context: TyCtxt<'a>, ty: Ty<'a>) -> Option<Ty<'a>> {
+    ty.walk()
+        .filter_map(GenericArg::as_type)
+        .find(|t| is_result(context, *t))
+}
+
 /// Extract the Result type from any future.
-fn extract_result_from_future<'a>(context: TyCtxt<'a>, ty: Ty<'a>) -> Option<GenericArg<'a>> {
+fn extract_result_from_future<'a>(context: TyCtxt<'a>, ty: Ty<'a>) -> Option<Ty<'a>> {
     for t in ty.walk() {
         if let Some(typ) = t.as_type() {
             if let TyKind::Alias(_kind, alias) = typ.kind() {
                 if let TyKind::Coroutine(_def_id, args) =
                     context.type_of(alias.def_id).instantiate_identity().kind()
                 {
-                    for arg in *args {
-                        let format = format!("{arg}");
-                        if format.starts_with("std::result::Result<") && format.ends_with('>') {
+                    for arg in args.types() {
+                        if is_result(context, arg) {
                             return Some(arg);
                         }
                     }
@@ -113,15 +393,28 @@ fn extract_result_from_future<'a>(context: TyCtxt<'a>, ty: Ty<'a>) -> Option<Gen
     None
 }
 
-/// Extract the error from a Result type.
-fn extract_error_from_result(opt: Option<GenericArg>) -> Option<String> {
-    let t = opt?;
-    for arg in t.walk() {
-        let f = format!("{arg}");
-        if format!("{t}").ends_with(&format!(", {f}>")) {
-            return Some(f);
+/// Get the output type of an async function's future.
+fn get_future_output<'a>(context: TyCtxt<'a>, ty: Ty<'a>) -> Option<Ty<'a>> {
+    for t in ty.walk() {
+        if let Some(typ) = t.as_type() {
+            if let TyKind::Alias(_kind, alias) = typ.kind() {
+                if let TyKind::Coroutine(_def_id, args) =
+                    context.type_of(alias.def_id).instantiate_identity().kind()
+                {
+                    return Some(args.as_coroutine().return_ty());
+                }
+            }
         }
     }
 
     None
 }
+
+/// Extract the error from a Result type, which is its second generic argument.
+fn extract_error_from_result(result: Ty) -> Option<Ty> {
+    if let TyKind::Adt(_adt, args) = result.kind() {
+        args.get(1)?.as_type()
+    } else {
+        None
+    }
+}
@@ -0,0 +1,134 @@
+//! The `cargo error-chains` subcommand.
+//!
+//! The analyzer links to the `rustc_driver` of the nightly toolchain it is built with, which is not on the library path
+//! outside of `cargo run`. This launcher doesn't, so it can find the sysroot of that toolchain,
+//! add its libraries to the library path, and then run the analyzer on the package in the current directory.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The nightly toolchain the analyzer is built with.
+const TOOLCHAIN: &str = "nightly-2024-05-18";
+
+/// The name of the analyzer binary, which is installed next to this one.
+const DRIVER: &str = "static-result-analyzer";
+
+/// Exit code when the toolchain or the analyzer could not be found, as the package can't be built without them.
+const EXIT_BUILD_FAILURE: i32 = 3;
+
+/// Entry point, sets up the environment of the analyzer and runs it with the provided arguments.
+fn main() {
+    // Cargo passes the name of the subcommand as the first argument
+    let mut args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "error-chains") {
+        args.remove(0);
+    }
+
+    let Some(sysroot) = get_sysroot() else {
+        eprintln!("Could not find the sysroot of the {TOOLCHAIN} toolchain!");
+        eprintln!("Install it using `rustup toolchain install {TOOLCHAIN} --component rustc-dev llvm-tools`.");
+        std::process::exit(EXIT_BUILD_FAILURE);
+    };
+
+    let driver = std::env::current_exe()
+        .expect("Could not get path to the subcommand!")
+        .with_file_name(format!("{DRIVER}{}", std::env::consts::EXE_SUFFIX));
+    if !driver.exists() {
+        eprintln!("Could not find the analyzer at {}!", driver.display());
+        std::process::exit(EXIT_BUILD_FAILURE);
+    }
+
+    let mut driver_command = Command::new(&driver);
+
+    // Analyze the package in the current directory by default, like other cargo subcommands
+    // An explicitly provided manifest comes later, so it takes precedence
+    if let Some(manifest_path) = locate_manifest() {
+        driver_command.arg("--manifest-path");
+        driver_command.arg(manifest_path);
+    }
+    driver_command.args(&args);
+
+    // The analyzer runs cargo with itself as the rustc wrapper, which should use the same toolchain
+    driver_command.env("RUSTUP_TOOLCHAIN", TOOLCHAIN);
+    let (name, value) = get_library_path(&sysroot);
+    driver_command.env(name, value);
+
+    exec(driver_command);
+}
+
+/// Get the sysroot of the toolchain the analyzer is built with, using `rustc --print sysroot`.
+fn get_sysroot() -> Option<PathBuf> {
+    let output = Command::new("rustc")
+        .arg("--print")
+        .arg("sysroot")
+        .env("RUSTUP_TOOLCHAIN", TOOLCHAIN)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8(output.stdout).expect("Invalid UTF8!");
+
+    Some(PathBuf::from(stdout.trim_end()))
+}
+
+/// Get the manifest of the package in the current directory (or one of its parents) using `cargo locate-project`.
+fn locate_manifest() -> Option<PathBuf> {
+    let output = Command::new("cargo")
+        .arg("locate-project")
+        .arg("--message-format")
+        .arg("plain")
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8(output.stdout).expect("Invalid UTF8!");
+
+    Some(PathBuf::from(stdout.trim_end()))
+}
+
+/// Get the library path variable with the libraries of the sysroot (e.g. `rustc_driver`) prepended to it.
+fn get_library_path(sysroot: &Path) -> (&'static str, OsString) {
+    let (name, dir) = if cfg!(windows) {
+        ("PATH", sysroot.join("bin"))
+    } else if cfg!(target_os = "macos") {
+        ("DYLD_FALLBACK_LIBRARY_PATH", sysroot.join("lib"))
+    } else {
+        ("LD_LIBRARY_PATH", sysroot.join("lib"))
+    };
+
+    let mut paths = vec![dir];
+    if let Some(current) = std::env::var_os(name) {
+        paths.extend(std::env::split_paths(&current));
+    }
+
+    (
+        name,
+        std::env::join_paths(paths).expect("Could not join library paths!"),
+    )
+}
+
+/// Replace this process with the given command, or run it and exit with its exit code where that is not possible.
+#[cfg(unix)]
+fn exec(mut command: Command) -> ! {
+    use std::os::unix::process::CommandExt;
+
+    let e = command.exec();
+    eprintln!("Could not run the analyzer!");
+    eprintln!("{e}");
+    std::process::exit(EXIT_BUILD_FAILURE);
+}
+
+/// Replace this process with the given command, or run it and exit with its exit code where that is not possible.
+#[cfg(not(unix))]
+fn exec(mut command: Command) -> ! {
+    let status = command.status().expect("Could not run the analyzer!");
+
+    std::process::exit(status.code().unwrap_or(EXIT_BUILD_FAILURE));
+}
@@ -0,0 +1,416 @@
+use crate::analysis::{Builder, Roots};
+use crate::graph::DotLocations;
+use std::process::Command;
+
+/// Exit code when the analysis found no swallowed errors.
+pub const EXIT_SUCCESS: i32 = 0;
+
+/// Exit code when the analysis found swallowed errors.
+pub const EXIT_FINDINGS: i32 = 1;
+
+/// Exit code when the command-line arguments are invalid.
+pub const EXIT_USAGE: i32 = 2;
+
+/// Exit code when the package could not be built, so it could not be analyzed.
+pub const EXIT_BUILD_FAILURE: i32 = 3;
+
+/// The help text printed by `--help`.
+pub const USAGE: &str = "\
+Statically analyzes how errors propagate through a Rust package.
+
+Usage: static-result-analyzer [OPTIONS] [-- CARGO_ARGS...]
+
+Options:
+      --manifest-path PATH       Path to the manifest of the package to analyze [default: Cargo.toml]
+  -o, --output PATH              Path to the output file [default: graph.dot, graph.json or graph.sarif]
+      --format dot|json|sarif    Format of the output file [default: dot]
+      --graph chains|calls       Output the error propagation chains or the full call graph [default: chains]
+      --locations label|tooltip  Show the source locations in the labels or as tooltips and links of the dot output
+      --builder hir|mir          Build the call graph from the HIR, or from the MIR to include desugared calls [default: hir]
+      --roots entry|public|tests Functions to start the analysis from [default: entry]
+      --root PATH                Start the analysis from the function with this path (e.g. module::function), can be repeated
+      --swallowed                List every call site where an error is swallowed, e.g. by unwrap or let _ =
+      --workspace                Analyze every crate in the workspace, instead of only the target crate and its workspace dependencies
+      --rebuild                  Clean and rebuild the package to record the compiler arguments, instead of analyzing the crates while checking them
+  -h, --help                     Print this help
+  -V, --version                  Print the version
+
+Cargo options:
+      --features FEATURES        Features to activate, can be repeated
+      --all-features             Activate all available features
+      --no-default-features      Do not activate the default feature
+      --target TRIPLE            Build for the target triple
+      --bin NAME                 Analyze the binary with this name
+      --lib                      Analyze the library
+      --example NAME             Analyze the example with this name
+      --test NAME                Analyze the integration test with this name
+      --bench NAME               Analyze the benchmark with this name
+      --all-targets              Analyze every target, writing a graph for each target next to the combined graph
+  -- CARGO_ARGS...               Pass the remaining arguments to cargo
+
+Paths are relative to the current directory.
+
+Exit codes:
+  0  The analysis found no swallowed errors
+  1  The analysis found swallowed errors
+  2  The arguments are invalid
+  3  The package could not be built
+";
+
+/// The format of the output file.
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    Dot,
+    Json,
+    Sarif,
+}
+
+/// The graph that is written to the output file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphKind {
+    /// The error propagation chains within the call graph.
+    Chains,
+    /// The full call graph.
+    Calls,
+}
+
+/// The target of the package to analyze.
+#[derive(Debug, Clone)]
+pub enum TargetCrate {
+    /// The binary, or the library if the package has none.
+    Main,
+    /// The binary with the given name.
+    Bin(String),
+    /// The library.
+    Lib,
+    /// The example with the given name.
+    Example(String),
+    /// The integration test with the given name.
+    Test(String),
+    /// The benchmark with the given name.
+    Bench(String),
+    /// Every target of the package.
+    All,
+}
+
+/// The options that are passed on to cargo when building the package.
+#[derive(Debug, Clone)]
+pub struct CargoOptions {
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    /// The target triple to build for.
+    pub target: Option<String>,
+    pub target_crate: TargetCrate,
+    /// The arguments after `--`, which are passed to cargo as is.
+    pub passthrough: Vec<String>,
+}
+
+/// The arguments provided to the analyzer.
+#[derive(Debug, Clone)]
+pub struct Arguments {
+    pub manifest_path: String,
+    pub output_path: String,
+    pub format: Format,
+    pub graph: GraphKind,
+    pub locations: DotLocations,
+    pub builder: Builder,
+    pub roots: Roots,
+    pub rebuild: bool,
+    pub workspace: bool,
+    pub swallowed: bool,
+    pub cargo: CargoOptions,
+}
+
+/// What the analyzer is asked to do.
+pub enum Invocation {
+    Analyze(Arguments),
+    Help,
+    Version,
+}
+
+impl CargoOptions {
+    /// Add the options to a cargo command, with the passed through arguments last.
+    pub fn add_args(&self, command: &mut Command) {
+        for features in &self.features {
+            command.arg("--features").arg(features);
+        }
+        if self.all_features {
+            command.arg("--all-features");
+        }
+        if self.no_default_features {
+            command.arg("--no-default-features");
+        }
+        if let Some(target) = &self.target {
+            command.arg("--target").arg(target);
+        }
+
+        match &self.target_crate {
+            TargetCrate::Main => {}
+            TargetCrate::Bin(name) => {
+                command.arg("--bin").arg(name);
+            }
+            TargetCrate::Lib => {
+                command.arg("--lib");
+            }
+            TargetCrate::Example(name) => {
+                command.arg("--example").arg(name);
+            }
+            TargetCrate::Test(name) => {
+                command.arg("--test").arg(name);
+            }
+            TargetCrate::Bench(name) => {
+                command.arg("--bench").arg(name);
+            }
+            TargetCrate::All => {
+                command.arg("--all-targets");
+            }
+        }
+
+        command.args(&self.passthrough);
+    }
+}
+
+/// Parse the command-line arguments (without the program name).
+/// Options can be given as `--name value` or `--name=value`, and the last occurrence of an option is used.
+/// Returns a description of the problem if the arguments are invalid.
+pub fn parse_arguments(args: &[String]) -> Result<Invocation, String> {
+    let mut manifest_path = String::from("Cargo.toml");
+    let mut output_path = None;
+    let mut format = Format::Dot;
+    let mut graph = GraphKind::Chains;
+    let mut locations = DotLocations::None;
+    let mut builder = Builder::Hir;
+    let mut roots = Roots::Entry;
+    let mut paths = vec![];
+    let mut rebuild = false;
+    let mut workspace = false;
+    let mut swallowed = false;
+    let mut cargo = CargoOptions {
+        features: vec![],
+        all_features: false,
+        no_default_features: false,
+        target: None,
+        target_crate: TargetCrate::Main,
+        passthrough: vec![],
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            cargo.passthrough.extend(args.by_ref().cloned());
+            break;
+        }
+
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+
+        // The value of an option, either after `=` or as the next argument
+        let mut value = || {
+            inline_value
+                .map(String::from)
+                .or_else(|| args.next().cloned())
+                .ok_or_else(|| format!("{name} requires a value"))
+        };
+
+        match name {
+            "-h" | "--help" => return Ok(Invocation::Help),
+            "-V" | "--version" => return Ok(Invocation::Version),
+            "--manifest-path" => manifest_path = value()?,
+            "-o" | "--output" => output_path = Some(value()?),
+            "--format" => {
+                format = match value()?.as_str() {
+                    "dot" => Format::Dot,
+                    "json" => Format::Json,
+                    "sarif" => Format::Sarif,
+                    other => return Err(invalid_value(name, other, "dot, json or sarif")),
+                }
+            }
+            "--graph" => {
+                graph = match value()?.as_str() {
+                    "chains" => GraphKind::Chains,
+                    "calls" => GraphKind::Calls,
+                    other => return Err(invalid_value(name, other, "chains or calls")),
+                }
+            }
+            "--locations" => {
+                locations = match value()?.as_str() {
+                    "label" => DotLocations::Label,
+                    "tooltip" => DotLocations::Tooltip,
+                    other => return Err(invalid_value(name, other, "label or tooltip")),
+                }
+            }
+            "--builder" => {
+                builder = match value()?.as_str() {
+                    "hir" => Builder::Hir,
+                    "mir" => Builder::Mir,
+                    other => return Err(invalid_value(name, other, "hir or mir")),
+                }
+            }
+            "--roots" => {
+                roots = match value()?.as_str() {
+                    "entry" => Roots::Entry,
+                    "public" => Roots::Public,
+                    "tests" => Roots::Tests,
+                    other => return Err(invalid_value(name, other, "entry, public or tests")),
+                }
+            }
+            "--root" => paths.push(value()?),
+            "--features" => cargo.features.push(value()?),
+            "--target" => cargo.target = Some(value()?),
+            "--bin" => cargo.target_crate = TargetCrate::Bin(value()?),
+            "--example" => cargo.target_crate = TargetCrate::Example(value()?),
+            "--test" => cargo.target_crate = TargetCrate::Test(value()?),
+            "--bench" => cargo.target_crate = TargetCrate::Bench(value()?),
+            _ if inline_value.is_some() => return Err(format!("{name} does not take a value")),
+            "--lib" => cargo.target_crate = TargetCrate::Lib,
+            "--all-targets" => cargo.target_crate = TargetCrate::All,
+            "--all-features" => cargo.all_features = true,
+            "--no-default-features" => cargo.no_default_features = true,
+            "--swallowed" => swallowed = true,
+            "--workspace" => workspace = true,
+            "--rebuild" => rebuild = true,
+            _ => return Err(format!("unknown argument {arg}")),
+        }
+    }
+
+    // Explicitly provided roots take precedence over the root selection mode
+    if !paths.is_empty() {
+        roots = Roots::Paths(paths);
+    }
+
+    // Rebuilding only finds the compiler arguments of a single target
+    if rebuild && matches!(cargo.target_crate, TargetCrate::All) {
+        return Err(String::from("--all-targets can't be used with --rebuild"));
+    }
+
+    let output_path = output_path.unwrap_or_else(|| {
+        String::from(match format {
+            Format::Dot => "graph.dot",
+            Format::Json => "graph.json",
+            Format::Sarif => "graph.sarif",
+        })
+    });
+
+    Ok(Invocation::Analyze(Arguments {
+        manifest_path,
+        output_path,
+        format,
+        graph,
+        locations,
+        builder,
+        roots,
+        rebuild,
+        workspace,
+        swallowed,
+        cargo,
+    }))
+}
+
+/// Describe an invalid value of an option.
+fn invalid_value(name: &str, value: &str, expected: &str) -> String {
+    format!("invalid value {value} for {name}, expected {expected}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Invocation, String> {
+        let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+        parse_arguments(&args)
+    }
+
+    fn analyze(args: &[&str]) -> Arguments {
+        match parse(args) {
+            Ok(Invocation::Analyze(arguments)) => arguments,
+            Ok(_) => panic!("expected arguments to analyze"),
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let arguments = analyze(&[]);
+        assert_eq!(arguments.manifest_path, "Cargo.toml");
+        assert_eq!(arguments.output_path, "graph.dot");
+        assert_eq!(arguments.graph, GraphKind::Chains);
+        assert!(matches!(arguments.roots, Roots::Entry));
+        assert!(matches!(arguments.cargo.target_crate, TargetCrate::Main));
+    }
+
+    #[test]
+    fn values_after_equals_sign_or_as_next_argument() {
+        let arguments = analyze(&["--manifest-path=foo/Cargo.toml", "--format", "json"]);
+        assert_eq!(arguments.manifest_path, "foo/Cargo.toml");
+        assert!(matches!(arguments.format, Format::Json));
+        assert_eq!(arguments.output_path, "graph.json");
+
+        let arguments = analyze(&["--bin", "foo", "--features=a,b", "--features", "c"]);
+        assert!(matches!(arguments.cargo.target_crate, TargetCrate::Bin(name) if name == "foo"));
+        assert_eq!(arguments.cargo.features, ["a,b", "c"]);
+    }
+
+    #[test]
+    fn repeated_roots() {
+        let arguments = analyze(&["--roots", "tests", "--root", "a::b", "--root=c"]);
+        assert!(matches!(arguments.roots, Roots::Paths(paths) if paths == ["a::b", "c"]));
+    }
+
+    #[test]
+    fn passthrough_after_double_dash() {
+        let arguments = analyze(&["--lib", "--", "--frozen", "--bin", "foo"]);
+        assert!(matches!(arguments.cargo.target_crate, TargetCrate::Lib));
+        assert_eq!(arguments.cargo.passthrough, ["--frozen", "--bin", "foo"]);
+    }
+
+    #[test]
+    fn help_and_version() {
+        assert!(matches!(
+            parse(&["--graph", "calls", "-h"]),
+            Ok(Invocation::Help)
+        ));
+        assert!(matches!(parse(&["--version"]), Ok(Invocation::Version)));
+    }
+
+    #[test]
+    fn flag_with_value() {
+        assert_eq!(
+            parse(&["--swallowed=true"]).err(),
+            Some(String::from("--swallowed does not take a value"))
+        );
+    }
+
+    #[test]
+    fn missing_value() {
+        assert_eq!(
+            parse(&["--output"]).err(),
+            Some(String::from("--output requires a value"))
+        );
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert_eq!(
+            parse(&["--format", "xml"]).err(),
+            Some(String::from(
+                "invalid value xml for --format, expected dot, json or sarif"
+            ))
+        );
+        assert_eq!(
+            parse(&["--frobnicate"]).err(),
+            Some(String::from("unknown argument --frobnicate"))
+        );
+    }
+
+    #[test]
+    fn all_targets_with_rebuild() {
+        assert_eq!(
+            parse(&["--all-targets", "--rebuild"]).err(),
+            Some(String::from("--all-targets can't be used with --rebuild"))
+        );
+        assert!(parse(&["--bin", "foo", "--rebuild"]).is_ok());
+    }
+}
@@ -1,14 +1,42 @@
 use dot::{Edges, Id, Kind, LabelText, Nodes, Style};
 use rustc_hir::def_id::DefId;
 use rustc_hir::HirId;
+use rustc_middle::ty::{Ty, TyCtxt, TyKind};
+use rustc_span::Span;
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::cell::Cell;
 use std::cmp::PartialEq;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 
 #[derive(Debug, Clone)]
 pub struct CallGraph {
     pub nodes: Vec<CallNode>,
     pub edges: Vec<CallEdge>,
     pub crate_name: String,
+    /// The id of the node of each function.
+    node_map: HashMap<CallNodeKind, usize>,
+    /// The id of the node of each local function, by its `HirId`.
+    local_fn_nodes: HashMap<HirId, usize>,
+    /// The ids of the outgoing edges of each node.
+    outgoing: Vec<Vec<usize>>,
+    /// The ids of the incoming edges of each node.
+    incoming: Vec<Vec<usize>>,
+    /// Where the locations are shown in the dot output that is being rendered.
+    dot_locations: Cell<DotLocations>,
+}
+
+/// Where to show the locations of the nodes and edges in the dot output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DotLocations {
+    /// Don't show the locations.
+    #[default]
+    None,
+    /// Add the locations to the labels.
+    Label,
+    /// Add the locations as `tooltip` and `URL` attributes.
+    Tooltip,
 }
 
 #[derive(Debug, Clone)]
@@ -17,22 +45,170 @@ pub struct CallNode {
     pub label: String,
     pub kind: CallNodeKind,
     pub panics: bool,
+    pub panic_sources: Vec<PanicSource>,
+    /// The name of the crate the function is defined in.
+    pub krate: String,
+    /// The location of the definition of the function.
+    pub location: Option<Location>,
+    /// Whether this is a trait method of which the implementation is not known statically.
+    pub trait_dispatch: bool,
+    /// Whether this is the place where an error is constructed, rather than a function.
+    pub origin: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum CallNodeKind {
     LocalFn(DefId, HirId),
     NonLocalFn(DefId),
+    /// A trait method called through dynamic dispatch or a generic parameter,
+    /// which could not be resolved to an implementation.
+    TraitFn(DefId),
+    /// A trait method called on a trait object (e.g. `Box<dyn Trait>`),
+    /// which may call any of the local implementations of the method.
+    VirtualFn(DefId),
+    /// The construction of an error within a local function (e.g. `Err(MyError::Parse)` or `bail!`),
+    /// identified by the function and the index of the construction within it.
+    ErrorOrigin(DefId, usize),
+    /// A function loaded from a crate summary, identified by its stable `DefPathHash`.
+    Summarized(String),
+}
+
+/// A reason why a function can panic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PanicSource {
+    /// An explicit panic, e.g. `panic!`, `todo!` or `unimplemented!`.
+    Panic,
+    /// An `unreachable!`.
+    Unreachable,
+    /// An assertion, e.g. `assert!`, or an `Assert` terminator that is not a bounds or overflow check.
+    Assert,
+    /// Calling `unwrap` on an `Option` or `Result`.
+    Unwrap,
+    /// Calling `expect` on an `Option` or `Result`.
+    Expect,
+    /// Indexing an array, slice or other collection out of bounds.
+    Index,
+    /// Arithmetic overflow, or division by zero.
+    Overflow,
+    /// Calling the named function, which can panic.
+    Call(String),
 }
 
 #[derive(Debug, Clone)]
 pub struct CallEdge {
+    id: usize,
     pub from: usize,
     pub to: usize,
-    pub call_id: HirId,
+    /// The call expression, which is `None` for edges loaded from a crate summary.
+    pub call_id: Option<HirId>,
     pub ty: Option<String>,
     pub propagates: bool,
     pub is_error: bool,
+    /// The error type of the returned `Result`, if any.
+    pub error: Option<ErrorType>,
+    /// The type alias through which the `Result` was returned (e.g. `std::io::Result`), if any.
+    pub alias: Option<String>,
+    /// The Try type through which the returned value is handled (e.g. `?` on an `Option`), if any.
+    pub try_kind: Option<TryKind>,
+    /// The conversion of the error by the try operator, if the edge propagates an error through one.
+    pub conversion: Option<ErrorConversion>,
+    /// The combinators (e.g. `map_err` or `context`) the returned value flows through, in order.
+    pub combinators: Vec<Combinator>,
+    /// Whether the error was wrapped (e.g. `Err(e.into())`) before it was returned through local variables,
+    /// if it is found to be propagated by following it through them.
+    pub wrapped: Option<bool>,
+    /// The stable `DefPathHash` of the `From` impl the error is converted through, if any,
+    /// which identifies it across crate summaries like the functions they contain.
+    pub from_impl: Option<String>,
+    /// What happens to the error, if the edge returns an error that is not propagated.
+    pub sink: Option<ErrorSink>,
+    /// The location of the call.
+    pub location: Option<Location>,
+    /// Whether this is a call that may be made on behalf of the caller, rather than an actual call:
+    /// one of the possible targets of a call on a trait object, or a closure called by the function it is passed to.
+    pub may_call: bool,
+}
+
+/// What happens to an error that is not propagated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorSink {
+    /// `unwrap` or `unwrap_err`, which panics instead.
+    Unwrap,
+    /// `expect` or `expect_err`, which panics instead.
+    Expect,
+    /// `unwrap_or`, `unwrap_or_else` or `unwrap_or_default`, which replaces the error with a value.
+    UnwrapOr,
+    /// `ok` or `err`, which turns the error into an `Option`.
+    Ok,
+    /// `let _ = ...`, or binding it to an unused variable.
+    LetUnderscore,
+    /// Discarding it as a statement, e.g. `call();`.
+    Discard,
+    /// Handling it using `match` or `if let`.
+    Handled,
+    /// Mapping it to another error using `map_err`, to return it later.
+    MapErr,
+    /// Printing or logging it, and continuing.
+    Logged,
+    /// Anything else, e.g. storing it in a variable or passing it to another function.
+    Other,
+}
+
+/// A location in the source code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The conversion of an error by the try operator, from the error of the operand to that of the enclosing function.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorConversion {
+    pub source: ErrorType,
+    pub target: ErrorType,
+    /// The name of the `From` impl used for the conversion, if the types differ.
+    pub via: Option<String>,
+}
+
+/// A combinator method on a `Result` or `Option` (e.g. `map_err`), or context method of an error handling crate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Combinator {
+    pub name: String,
+    /// The error type after the combinator, if it returns a `Result`.
+    pub error: Option<ErrorType>,
+}
+
+/// A type implementing `Try`, which the try operator (`?`) can be used on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TryKind {
+    Result,
+    /// An `Option`, of which `None` short-circuits.
+    Option,
+    Poll,
+    ControlFlow,
+    /// Any other type implementing `Try`, with its name.
+    Other(String),
+}
+
+/// The kind of value that is propagated along a chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChainKind {
+    /// An error, returned in a `Result`.
+    Result,
+    /// A `None`, returned in an `Option`.
+    Option,
+}
+
+/// The type of an error, extracted from the error parameter of a `Result`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorType {
+    /// The full type, as printed by the compiler.
+    pub name: String,
+    /// The path to the definition of the type, if it is an ADT (e.g. `std::io::Error`).
+    pub def_path: Option<String>,
+    /// The generic type arguments of the type, if it is an ADT.
+    pub args: Vec<ErrorType>,
 }
 
 impl<'a> dot::Labeller<'a, CallNode, CallEdge> for CallGraph {
@@ -47,11 +223,39 @@ impl<'a> dot::Labeller<'a, CallNode, CallEdge> for CallGraph {
     }
 
     fn node_label(&self, n: &CallNode) -> LabelText<'a> {
-        LabelText::label(n.label.clone())
+        let location = get_location_label(self.dot_locations.get(), n.location.as_ref());
+
+        if n.panic_sources.is_empty() {
+            LabelText::escaped(format!("{}{location}", escape_label(&n.label)))
+        } else {
+            let sources: Vec<String> = n.panic_sources.iter().map(ToString::to_string).collect();
+            LabelText::escaped(format!(
+                "{}\\npanics: {}{location}",
+                escape_label(&n.label),
+                escape_label(&sources.join(", "))
+            ))
+        }
     }
 
     fn edge_label(&self, e: &CallEdge) -> LabelText<'a> {
-        LabelText::label(e.ty.clone().unwrap_or(String::from("unknown")))
+        let location = get_location_label(self.dot_locations.get(), e.location.as_ref());
+        LabelText::escaped(format!("{}{location}", escape_label(&e.label())))
+    }
+
+    fn node_shape(&'a self, n: &CallNode) -> Option<LabelText<'a>> {
+        if n.origin {
+            Some(LabelText::label("diamond"))
+        } else {
+            None
+        }
+    }
+
+    fn node_style(&'a self, n: &CallNode) -> Style {
+        if n.trait_dispatch {
+            Style::Dashed
+        } else {
+            Style::None
+        }
     }
 
     fn node_color(&'a self, n: &CallNode) -> Option<LabelText<'a>> {
@@ -63,7 +267,9 @@ impl<'a> dot::Labeller<'a, CallNode, CallEdge> for CallGraph {
     }
 
     fn edge_color(&'a self, e: &CallEdge) -> Option<LabelText<'a>> {
-        if e.is_error && e.propagates {
+        if e.chain_kind() == Some(ChainKind::Option) && e.propagates {
+            Some(LabelText::label("darkorange"))
+        } else if e.is_error && e.propagates {
             Some(LabelText::label("purple"))
         } else if e.is_error {
             Some(LabelText::label("red"))
@@ -75,7 +281,9 @@ impl<'a> dot::Labeller<'a, CallNode, CallEdge> for CallGraph {
     }
 
     fn edge_style(&'a self, e: &CallEdge) -> Style {
-        if e.is_error || e.propagates {
+        if e.chain_kind() == Some(ChainKind::Option) && e.propagates {
+            Style::Dashed
+        } else if e.is_error || e.propagates {
             Style::None
         } else {
             Style::Dotted
@@ -89,15 +297,12 @@ impl<'a> dot::Labeller<'a, CallNode, CallEdge> for CallGraph {
 
 impl<'a> dot::GraphWalk<'a, CallNode, CallEdge> for CallGraph {
     fn nodes(&'a self) -> Nodes<'a, CallNode> {
-        let mut nodes = vec![];
-        for edge in &self.edges {
-            if !nodes.contains(&self.nodes[edge.from]) {
-                nodes.push(self.nodes[edge.from].clone());
-            }
-            if !nodes.contains(&self.nodes[edge.to]) {
-                nodes.push(self.nodes[edge.to].clone());
-            }
-        }
+        let nodes = self
+            .nodes
+            .iter()
+            .filter(|node| self.has_edges(node.id))
+            .cloned()
+            .collect();
         Cow::Owned(nodes)
     }
 
@@ -119,12 +324,27 @@ pub struct ChainGraph {
     pub nodes: Vec<ChainNode>,
     pub edges: Vec<ChainEdge>,
     pub crate_name: String,
+    pub chains: Vec<Chain>,
+    /// Where the locations are shown in the dot output that is being rendered.
+    dot_locations: Cell<DotLocations>,
+    /// The ids of the outgoing edges of each node.
+    outgoing: Vec<Vec<usize>>,
+    /// The ids of the incoming edges of each node.
+    incoming: Vec<Vec<usize>>,
+}
+
+/// A single propagation chain, referring to the edges of the call graph it was created from.
+#[derive(Debug, Clone)]
+pub struct Chain {
+    pub kind: ChainKind,
+    pub edges: Vec<usize>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ChainNode {
     id: usize,
     label: String,
+    location: Option<Location>,
 }
 
 #[derive(Debug, Clone)]
@@ -132,6 +352,9 @@ pub struct ChainEdge {
     from: usize,
     to: usize,
     label: Option<String>,
+    kind: ChainKind,
+    may_call: bool,
+    location: Option<Location>,
 }
 
 impl<'a> dot::Labeller<'a, ChainNode, ChainEdge> for ChainGraph {
@@ -146,25 +369,43 @@ impl<'a> dot::Labeller<'a, ChainNode, ChainEdge> for ChainGraph {
     }
 
     fn node_label(&self, n: &ChainNode) -> LabelText<'a> {
-        LabelText::label(n.label.clone())
+        let location = get_location_label(self.dot_locations.get(), n.location.as_ref());
+        LabelText::escaped(format!("{}{location}", escape_label(&n.label)))
     }
 
     fn edge_label(&self, e: &ChainEdge) -> LabelText<'a> {
-        LabelText::label(e.label.clone().unwrap_or(String::from("unknown")))
+        let label = e.label.clone().unwrap_or(String::from("unknown"));
+        let location = get_location_label(self.dot_locations.get(), e.location.as_ref());
+        LabelText::escaped(format!("{}{location}", escape_label(&label)))
+    }
+
+    fn edge_color(&'a self, e: &ChainEdge) -> Option<LabelText<'a>> {
+        match e.kind {
+            ChainKind::Result => None,
+            ChainKind::Option => Some(LabelText::label("darkorange")),
+        }
+    }
+
+    fn edge_style(&'a self, e: &ChainEdge) -> Style {
+        if e.may_call {
+            return Style::Dotted;
+        }
+
+        match e.kind {
+            ChainKind::Result => Style::None,
+            ChainKind::Option => Style::Dashed,
+        }
     }
 }
 
 impl<'a> dot::GraphWalk<'a, ChainNode, ChainEdge> for ChainGraph {
     fn nodes(&'a self) -> Nodes<'a, ChainNode> {
-        let mut nodes = vec![];
-        for edge in &self.edges {
-            if !nodes.contains(&self.nodes[edge.from]) {
-                nodes.push(self.nodes[edge.from].clone());
-            }
-            if !nodes.contains(&self.nodes[edge.to]) {
-                nodes.push(self.nodes[edge.to].clone());
-            }
-        }
+        let nodes = self
+            .nodes
+            .iter()
+            .filter(|node| self.has_edges(node.id))
+            .cloned()
+            .collect();
         Cow::Owned(nodes)
     }
 
@@ -188,78 +429,117 @@ impl CallGraph {
             nodes: Vec::new(),
             edges: Vec::new(),
             crate_name,
+            node_map: HashMap::new(),
+            local_fn_nodes: HashMap::new(),
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+            dot_locations: Cell::new(DotLocations::None),
         }
     }
 
     /// Add a node to this graph, returning its id.
     pub fn add_node(&mut self, label: &str, node_kind: CallNodeKind) -> usize {
-        let node = CallNode::new(self.nodes.len(), label, node_kind);
+        let node = CallNode::new(self.nodes.len(), label, node_kind.clone());
         let id = node.id();
+
+        if let CallNodeKind::LocalFn(_def_id, hir_id) = node_kind {
+            self.local_fn_nodes.insert(hir_id, id);
+        }
+        self.node_map.insert(node_kind, id);
+        self.outgoing.push(Vec::new());
+        self.incoming.push(Vec::new());
+
         self.nodes.push(node);
         id
     }
 
     /// Add an edge between two nodes to this graph.
-    pub fn add_edge(&mut self, edge: CallEdge) {
+    pub fn add_edge(&mut self, mut edge: CallEdge) {
+        edge.id = self.edges.len();
+        self.outgoing[edge.from].push(edge.id);
+        self.incoming[edge.to].push(edge.id);
         self.edges.push(edge);
     }
 
     /// Find a node of `LocalFn` kind.
     pub fn find_local_fn_node(&self, id: HirId) -> Option<CallNode> {
-        for node in &self.nodes {
-            if let CallNodeKind::LocalFn(_def_id, hir_id) = node.kind {
-                if hir_id == id {
-                    return Some(node.clone());
-                }
-            }
-        }
-
-        None
+        self.local_fn_nodes
+            .get(&id)
+            .map(|node_id| self.nodes[*node_id].clone())
     }
 
     /// Find a node of `NonLocalFn` kind.
     pub fn find_non_local_fn_node(&self, id: DefId) -> Option<CallNode> {
-        for node in &self.nodes {
-            if let CallNodeKind::NonLocalFn(def_id) = node.kind {
-                if def_id == id {
-                    return Some(node.clone());
-                }
-            }
-        }
+        self.find_node(&CallNodeKind::NonLocalFn(id))
+    }
 
-        None
+    /// Find a node of the given kind.
+    pub fn find_node(&self, kind: &CallNodeKind) -> Option<CallNode> {
+        self.node_map
+            .get(kind)
+            .map(|node_id| self.nodes[*node_id].clone())
     }
 
+    /// Get the edges of the calls made by a node.
     pub fn get_outgoing_edges(&self, node_id: usize) -> Vec<&CallEdge> {
-        let mut res = vec![];
+        self.outgoing[node_id]
+            .iter()
+            .map(|edge_id| &self.edges[*edge_id])
+            .collect()
+    }
 
-        for edge in &self.edges {
-            if edge.from == node_id {
-                res.push(edge);
-            }
-        }
+    /// Get the edges of the calls made to a node.
+    pub fn get_incoming_edges(&self, node_id: usize) -> Vec<&CallEdge> {
+        self.incoming[node_id]
+            .iter()
+            .map(|edge_id| &self.edges[*edge_id])
+            .collect()
+    }
 
-        res
+    /// Check whether a node is the caller or callee of any call.
+    fn has_edges(&self, node_id: usize) -> bool {
+        !self.outgoing[node_id].is_empty() || !self.incoming[node_id].is_empty()
     }
 
-    /// Convert this graph to dot representation.
-    pub fn to_dot(&self) -> String {
+    /// Convert this graph to dot representation, showing the locations of the nodes and edges as selected.
+    pub fn to_dot(&self, locations: DotLocations) -> String {
         let mut buf = Vec::new();
 
+        self.dot_locations.set(locations);
         dot::render(self, &mut buf).unwrap();
 
-        String::from_utf8(buf).unwrap()
+        let dot = String::from_utf8(buf).unwrap();
+        if locations == DotLocations::Tooltip {
+            // The nodes are rendered first, followed by the edges
+            let nodes = dot::GraphWalk::nodes(self);
+            let node_locations = nodes.iter().map(|node| node.location.as_ref());
+            let edge_locations = self.edges.iter().map(|edge| edge.location.as_ref());
+            add_location_attributes(&dot, node_locations.chain(edge_locations))
+        } else {
+            dot
+        }
     }
 }
 
 impl CallNode {
     /// Create a new node.
     fn new(node_id: usize, label: &str, node_type: CallNodeKind) -> Self {
+        let trait_dispatch = matches!(
+            node_type,
+            CallNodeKind::TraitFn(_) | CallNodeKind::VirtualFn(_)
+        );
+        let origin = matches!(node_type, CallNodeKind::ErrorOrigin(_, _));
+
         CallNode {
             id: node_id,
             label: String::from(label),
             kind: node_type,
             panics: false,
+            panic_sources: Vec::new(),
+            krate: String::new(),
+            location: None,
+            trait_dispatch,
+            origin,
         }
     }
 
@@ -280,47 +560,202 @@ impl CallNodeKind {
         CallNodeKind::NonLocalFn(id)
     }
 
+    /// Get a new `TraitFn`.
+    pub fn trait_fn(id: DefId) -> Self {
+        CallNodeKind::TraitFn(id)
+    }
+
+    /// Get a new `VirtualFn`.
+    pub fn virtual_fn(id: DefId) -> Self {
+        CallNodeKind::VirtualFn(id)
+    }
+
+    /// Get a new `ErrorOrigin`.
+    pub fn error_origin(fn_id: DefId, index: usize) -> Self {
+        CallNodeKind::ErrorOrigin(fn_id, index)
+    }
+
+    /// Get a new `Summarized`.
+    pub fn summarized(hash: String) -> Self {
+        CallNodeKind::Summarized(hash)
+    }
+
     /// Extract the `DefId` from this node.
+    /// Panics for `Summarized` nodes, as those are not part of the current compilation session.
     pub fn def_id(&self) -> DefId {
         match self {
             CallNodeKind::LocalFn(def_id, _hir_id) => *def_id,
-            CallNodeKind::NonLocalFn(def_id) => *def_id,
+            CallNodeKind::NonLocalFn(def_id)
+            | CallNodeKind::TraitFn(def_id)
+            | CallNodeKind::VirtualFn(def_id)
+            | CallNodeKind::ErrorOrigin(def_id, _) => *def_id,
+            CallNodeKind::Summarized(_hash) => panic!("Summarized node has no DefId!"),
+        }
+    }
+}
+
+impl Display for PanicSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PanicSource::Panic => write!(f, "panic"),
+            PanicSource::Unreachable => write!(f, "unreachable"),
+            PanicSource::Assert => write!(f, "assertion"),
+            PanicSource::Unwrap => write!(f, "unwrap"),
+            PanicSource::Expect => write!(f, "expect"),
+            PanicSource::Index => write!(f, "indexing"),
+            PanicSource::Overflow => write!(f, "overflow"),
+            PanicSource::Call(label) => write!(f, "calls {label}"),
         }
     }
 }
 
 impl CallEdge {
     /// Create a new edge.
-    pub fn new(from: usize, to: usize, call_id: HirId, propagates: bool) -> Self {
+    pub fn new(from: usize, to: usize, call_id: Option<HirId>, propagates: bool) -> Self {
         CallEdge {
+            id: 0,
             from,
             to,
             call_id,
             ty: None,
             propagates,
             is_error: false,
+            error: None,
+            alias: None,
+            try_kind: None,
+            conversion: None,
+            combinators: Vec::new(),
+            wrapped: None,
+            from_impl: None,
+            sink: None,
+            location: None,
+            may_call: false,
+        }
+    }
+
+    /// Get the kind of chain this edge can be part of, if any.
+    pub fn chain_kind(&self) -> Option<ChainKind> {
+        match self.try_kind {
+            Some(TryKind::Option) => Some(ChainKind::Option),
+            _ if self.is_error => Some(ChainKind::Result),
+            _ => None,
+        }
+    }
+
+    /// Get the id of this edge.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Get the label of this edge: its type annotated with the alias it was returned through,
+    /// or the conversion of the error if it is converted by the try operator or by combinators (e.g. `map_err`).
+    pub fn label(&self) -> String {
+        if let Some(conversion) = &self.conversion {
+            if let Some(via) = &conversion.via {
+                return format!(
+                    "{} -> {} (via {via})",
+                    conversion.source.name, conversion.target.name
+                );
+            }
+        }
+
+        let ty = self.ty.clone().unwrap_or(String::from("unknown"));
+
+        if let Some(target) = self.combinators.iter().rev().find_map(|c| c.error.as_ref()) {
+            if self.error.as_ref() != Some(target) {
+                let via: Vec<&str> = self.combinators.iter().map(|c| c.name.as_str()).collect();
+                return format!("{ty} -> {} (via {})", target.name, via.join(", "));
+            }
+        }
+
+        if let Some(alias) = &self.alias {
+            format!("{ty} ({alias})")
+        } else {
+            ty
         }
     }
 }
 
-impl PartialEq for CallNode {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id && self.kind == other.kind
+impl ErrorSink {
+    /// Check whether the error is swallowed, instead of being handled or passed on.
+    pub fn is_swallowed(self) -> bool {
+        matches!(
+            self,
+            ErrorSink::Unwrap
+                | ErrorSink::Expect
+                | ErrorSink::UnwrapOr
+                | ErrorSink::Ok
+                | ErrorSink::LetUnderscore
+                | ErrorSink::Discard
+                | ErrorSink::Logged
+        )
     }
 }
 
-impl PartialEq for CallNodeKind {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (CallNodeKind::LocalFn(def_id1, hir_id1), CallNodeKind::LocalFn(def_id2, hir_id2)) => {
-                def_id1 == def_id2 && hir_id1 == hir_id2
+impl Display for ErrorSink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorSink::Unwrap => write!(f, "unwrap"),
+            ErrorSink::Expect => write!(f, "expect"),
+            ErrorSink::UnwrapOr => write!(f, "unwrap_or"),
+            ErrorSink::Ok => write!(f, "ok"),
+            ErrorSink::LetUnderscore => write!(f, "let _ ="),
+            ErrorSink::Discard => write!(f, "discarded"),
+            ErrorSink::Handled => write!(f, "handled"),
+            ErrorSink::MapErr => write!(f, "map_err"),
+            ErrorSink::Logged => write!(f, "logged"),
+            ErrorSink::Other => write!(f, "other"),
+        }
+    }
+}
+
+impl Location {
+    /// Get the location of the start of a span.
+    pub fn new(context: TyCtxt, span: Span) -> Self {
+        let loc = context.sess.source_map().lookup_char_pos(span.lo());
+
+        Location {
+            file: loc.file.name.prefer_local().to_string(),
+            line: loc.line,
+            column: loc.col.0 + 1,
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+impl ErrorType {
+    /// Create a new error type from the given type.
+    pub fn new<'tcx>(context: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Self {
+        if let TyKind::Adt(adt, args) = ty.kind() {
+            ErrorType {
+                name: format!("{ty}"),
+                def_path: Some(context.def_path_str(adt.did())),
+                args: args
+                    .types()
+                    .map(|arg| ErrorType::new(context, arg))
+                    .collect(),
+            }
+        } else {
+            ErrorType {
+                name: format!("{ty}"),
+                def_path: None,
+                args: Vec::new(),
             }
-            (CallNodeKind::NonLocalFn(id1), CallNodeKind::NonLocalFn(id2)) => id1 == id2,
-            _ => false,
         }
     }
 }
 
+impl PartialEq for CallNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.kind == other.kind
+    }
+}
+
 impl PartialEq for CallEdge {
     fn eq(&self, other: &Self) -> bool {
         self.to == other.to && self.from == other.from
@@ -334,42 +769,92 @@ impl ChainGraph {
             nodes: Vec::new(),
             edges: Vec::new(),
             crate_name,
+            chains: Vec::new(),
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+            dot_locations: Cell::new(DotLocations::None),
         }
     }
 
-    pub fn add_node(&mut self, label: String) -> usize {
+    pub fn add_node(&mut self, label: String, location: Option<Location>) -> usize {
         let id = self.nodes.len();
 
-        self.nodes.push(ChainNode::new(id, label));
+        self.nodes.push(ChainNode::new(id, label, location));
+        self.outgoing.push(Vec::new());
+        self.incoming.push(Vec::new());
 
         id
     }
 
-    pub fn add_edge(&mut self, from: usize, to: usize, label: Option<String>) {
-        self.edges.push(ChainEdge::new(from, to, label));
+    pub fn add_edge(
+        &mut self,
+        from: usize,
+        to: usize,
+        label: Option<String>,
+        kind: ChainKind,
+        may_call: bool,
+        location: Option<Location>,
+    ) {
+        self.outgoing[from].push(self.edges.len());
+        self.incoming[to].push(self.edges.len());
+        self.edges
+            .push(ChainEdge::new(from, to, label, kind, may_call, location));
+    }
+
+    /// Check whether a node is part of any edge.
+    fn has_edges(&self, node_id: usize) -> bool {
+        !self.outgoing[node_id].is_empty() || !self.incoming[node_id].is_empty()
     }
 
-    /// Convert this graph to dot representation.
-    pub fn to_dot(&self) -> String {
+    /// Convert this graph to dot representation, showing the locations of the nodes and edges as selected.
+    pub fn to_dot(&self, locations: DotLocations) -> String {
         let mut buf = Vec::new();
 
+        self.dot_locations.set(locations);
         dot::render(self, &mut buf).unwrap();
 
-        String::from_utf8(buf).unwrap()
+        let dot = String::from_utf8(buf).unwrap();
+        if locations == DotLocations::Tooltip {
+            // The nodes are rendered first, followed by the edges
+            let nodes = dot::GraphWalk::nodes(self);
+            let node_locations = nodes.iter().map(|node| node.location.as_ref());
+            let edge_locations = self.edges.iter().map(|edge| edge.location.as_ref());
+            add_location_attributes(&dot, node_locations.chain(edge_locations))
+        } else {
+            dot
+        }
     }
 }
 
 impl ChainNode {
     /// Create a new node.
-    fn new(id: usize, label: String) -> Self {
-        ChainNode { id, label }
+    fn new(id: usize, label: String, location: Option<Location>) -> Self {
+        ChainNode {
+            id,
+            label,
+            location,
+        }
     }
 }
 
 impl ChainEdge {
     /// Create a new edge.
-    pub fn new(from: usize, to: usize, label: Option<String>) -> Self {
-        ChainEdge { from, to, label }
+    pub fn new(
+        from: usize,
+        to: usize,
+        label: Option<String>,
+        kind: ChainKind,
+        may_call: bool,
+        location: Option<Location>,
+    ) -> Self {
+        ChainEdge {
+            from,
+            to,
+            label,
+            kind,
+            may_call,
+            location,
+        }
     }
 }
 
@@ -384,3 +869,46 @@ impl PartialEq for ChainEdge {
         self.to == other.to && self.from == other.from
     }
 }
+
+/// Escape a label for use in an escaped dot string, in which backslashes start an escape sequence.
+/// Quotes are escaped by the dot crate itself.
+fn escape_label(label: &str) -> String {
+    label.replace('\\', "\\\\")
+}
+
+/// Get the location to append to a dot label, if locations are shown in labels.
+fn get_location_label(locations: DotLocations, location: Option<&Location>) -> String {
+    match (locations, location) {
+        (DotLocations::Label, Some(location)) => {
+            format!("\\n{}", escape_label(&location.to_string()))
+        }
+        _ => String::new(),
+    }
+}
+
+/// Add a `tooltip` and `URL` attribute with its location to each statement of rendered dot,
+/// as the dot crate can't render them. The locations are given in the order the statements are rendered.
+fn add_location_attributes<'a>(
+    dot: &str,
+    mut locations: impl Iterator<Item = Option<&'a Location>>,
+) -> String {
+    let mut res = String::new();
+
+    for line in dot.lines() {
+        match line.strip_suffix(';') {
+            Some(statement) if line.starts_with("    ") => {
+                if let Some(Some(location)) = locations.next() {
+                    let text = escape_label(&location.to_string()).replace('"', "\\\"");
+                    let file = escape_label(&location.file).replace('"', "\\\"");
+                    res.push_str(&format!("{statement}[tooltip=\"{text}\"][URL=\"{file}\"];"));
+                } else {
+                    res.push_str(line);
+                }
+            }
+            _ => res.push_str(line),
+        }
+        res.push('\n');
+    }
+
+    res
+}
@@ -0,0 +1,144 @@
+use crate::graph::{
+    CallGraph, ChainGraph, ChainKind, Combinator, ErrorConversion, ErrorSink, ErrorType, Location,
+    PanicSource, TryKind,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The version of the JSON output, which is incremented whenever its schema changes incompatibly.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct JsonGraph<'a> {
+    version: u32,
+    #[serde(rename = "crate")]
+    crate_name: &'a str,
+    nodes: Vec<JsonNode<'a>>,
+    edges: Vec<JsonEdge<'a>>,
+    chains: Vec<JsonChain<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonNode<'a> {
+    id: usize,
+    path: &'a str,
+    #[serde(rename = "crate")]
+    krate: &'a str,
+    location: Option<&'a Location>,
+    panics: bool,
+    panic_sources: &'a [PanicSource],
+    trait_dispatch: bool,
+    origin: bool,
+}
+
+#[derive(Serialize)]
+struct JsonEdge<'a> {
+    id: usize,
+    from: usize,
+    to: usize,
+    caller: &'a str,
+    callee: &'a str,
+    #[serde(rename = "crate")]
+    krate: &'a str,
+    location: Option<&'a Location>,
+    #[serde(rename = "type")]
+    ty: Option<&'a str>,
+    is_error: bool,
+    error: Option<&'a ErrorType>,
+    alias: Option<&'a str>,
+    propagates: bool,
+    try_kind: Option<&'a TryKind>,
+    conversion: Option<&'a ErrorConversion>,
+    /// The stable `DefPathHash` of the `From` impl the error is converted through, if any.
+    from_impl: Option<&'a str>,
+    combinators: &'a [Combinator],
+    wrapped: Option<bool>,
+    sink: Option<ErrorSink>,
+    may_call: bool,
+    /// The ids of the chains this edge is part of.
+    chains: Vec<usize>,
+}
+
+#[derive(Serialize)]
+struct JsonChain<'a> {
+    id: usize,
+    kind: ChainKind,
+    edges: &'a [usize],
+}
+
+/// Convert the call graph, along with the chains found in it, to a versioned JSON representation.
+pub fn to_json(call_graph: &CallGraph, chain_graph: &ChainGraph) -> String {
+    let nodes = call_graph
+        .nodes
+        .iter()
+        .map(|node| JsonNode {
+            id: node.id(),
+            path: &node.label,
+            krate: &node.krate,
+            location: node.location.as_ref(),
+            panics: node.panics,
+            panic_sources: &node.panic_sources,
+            trait_dispatch: node.trait_dispatch,
+            origin: node.origin,
+        })
+        .collect();
+
+    // The chains each edge is part of, in the order of the chains
+    let mut edge_chains: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (id, chain) in chain_graph.chains.iter().enumerate() {
+        for edge_id in &chain.edges {
+            let chains = edge_chains.entry(*edge_id).or_default();
+            if chains.last() != Some(&id) {
+                chains.push(id);
+            }
+        }
+    }
+
+    let edges = call_graph
+        .edges
+        .iter()
+        .map(|edge| JsonEdge {
+            id: edge.id(),
+            from: edge.from,
+            to: edge.to,
+            caller: &call_graph.nodes[edge.from].label,
+            callee: &call_graph.nodes[edge.to].label,
+            krate: &call_graph.nodes[edge.from].krate,
+            location: edge.location.as_ref(),
+            ty: edge.ty.as_deref(),
+            is_error: edge.is_error,
+            error: edge.error.as_ref(),
+            alias: edge.alias.as_deref(),
+            propagates: edge.propagates,
+            try_kind: edge.try_kind.as_ref(),
+            conversion: edge.conversion.as_ref(),
+            from_impl: edge.from_impl.as_deref(),
+            combinators: &edge.combinators,
+            wrapped: edge.wrapped,
+            sink: edge.sink,
+            may_call: edge.may_call,
+            chains: edge_chains.remove(&edge.id()).unwrap_or_default(),
+        })
+        .collect();
+
+    let chains = chain_graph
+        .chains
+        .iter()
+        .enumerate()
+        .map(|(id, chain)| JsonChain {
+            id,
+            kind: chain.kind,
+            edges: &chain.edges,
+        })
+        .collect();
+
+    let graph = JsonGraph {
+        version: SCHEMA_VERSION,
+        crate_name: &call_graph.crate_name,
+        nodes,
+        edges,
+        chains,
+    };
+
+    serde_json::to_string_pretty(&graph).expect("Could not serialize graph!")
+}
@@ -1,7 +1,12 @@
 #![feature(rustc_private)]
 
 mod analysis;
+mod cli;
 mod graph;
+mod json;
+mod sarif;
+mod summary;
+mod targets;
 
 extern crate rustc_driver;
 extern crate rustc_hir;
@@ -9,13 +14,51 @@ extern crate rustc_interface;
 extern crate rustc_middle;
 extern crate rustc_parse;
 extern crate rustc_session;
-
+extern crate rustc_span;
+
+use analysis::{Builder, Roots};
+use cli::{
+    Arguments, CargoOptions, Format, GraphKind, Invocation, EXIT_BUILD_FAILURE, EXIT_FINDINGS,
+    EXIT_SUCCESS, EXIT_USAGE, USAGE,
+};
+use graph::{CallGraph, DotLocations};
 use rustc_driver::Compilation;
 use rustc_interface::interface::Compiler;
 use rustc_interface::Queries;
+use rustc_span::Symbol;
+use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use toml::Table;
+use summary::CrateSummary;
+use targets::{get_arg_value, Target, TargetKind};
+
+/// Environment variable through which the analyzer arguments are passed to the workspace wrapper.
+const ARGS_ENV: &str = "STATIC_RESULT_ANALYZER_ARGS";
+
+/// Environment variable that is unique for each run, so cargo re-runs the wrapper on the analyzed crate.
+const RUN_ENV: &str = "STATIC_RESULT_ANALYZER_RUN";
+
+/// Separator between the arguments in `ARGS_ENV`.
+const ARGS_SEPARATOR: &str = "__STATIC_RESULT_ANALYZER__";
+
+/// Environment variable through which the selected targets are passed to the workspace wrapper, as JSON.
+const TARGETS_ENV: &str = "STATIC_RESULT_ANALYZER_TARGETS";
+
+/// Environment variable through which the directory to record the rustc invocations to is passed to the workspace wrapper,
+/// when finding the compiler arguments with `--rebuild`.
+const RECORD_ENV: &str = "STATIC_RESULT_ANALYZER_RECORD";
+
+/// A rustc invocation recorded by the workspace wrapper.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RustcInvocation {
+    /// The path to rustc, followed by its arguments.
+    args: Vec<String>,
+    /// The directory rustc was run in, to which the paths in the arguments are relative.
+    cwd: PathBuf,
+    /// The environment rustc was run in, including the variables set by cargo (e.g. `CARGO_PKG_NAME` and `OUT_DIR`)
+    /// and by build scripts through `cargo:rustc-env`, which `env!` may read.
+    env: Vec<(String, String)>,
+}
 
 /// Entry point, first sets up the compiler, and then runs it using the provided arguments.
 fn main() {
@@ -24,59 +67,426 @@ fn main() {
         rustc_session::EarlyDiagCtxt::new(rustc_session::config::ErrorOutputType::default());
 
     // Get command-line args
-    let args = rustc_driver::args::raw_args(&early_dcx)
-        .unwrap_or_else(|_| std::process::exit(rustc_driver::EXIT_FAILURE));
+    let args =
+        rustc_driver::args::raw_args(&early_dcx).unwrap_or_else(|_| std::process::exit(EXIT_USAGE));
+
+    // If cargo invoked us as the workspace wrapper to find the compiler arguments, record them and run rustc
+    if let Ok(record_dir) = std::env::var(RECORD_ENV) {
+        let exit_code = record_invocation(&args, Path::new(&record_dir));
+        std::process::exit(exit_code);
+    }
+
+    // If cargo invoked us as the workspace wrapper, the arguments are passed through the environment
+    if let Ok(wrapper_args) = std::env::var(ARGS_ENV) {
+        let analyzer_args: Vec<String> = wrapper_args
+            .split(ARGS_SEPARATOR)
+            .map(String::from)
+            .collect();
+
+        let exit_code = run_wrapper(&early_dcx, &args, &extract_arguments(&analyzer_args));
+        std::process::exit(exit_code);
+    }
 
     // Extract the arguments
-    let (relative_manifest_path, relative_output_path, remove_redundant) = extract_arguments(&args);
+    let arguments = extract_arguments(&args[1..]);
+
+    let manifest_path = get_manifest_path(&arguments.manifest_path);
+    let output_path = get_output_path(&arguments.output_path);
+
+    if !arguments.rebuild {
+        // Select the targets to analyze from the targets of the package
+        let tests = matches!(arguments.roots, Roots::Tests);
+        let Some(package) = targets::get_package(&manifest_path) else {
+            std::process::exit(EXIT_BUILD_FAILURE);
+        };
+        let targets = targets::select_targets(&package, &arguments.cargo.target_crate, tests);
+        if targets.is_empty() {
+            eprintln!(
+                "Could not find the selected target in package {}!",
+                package.name
+            );
+            std::process::exit(EXIT_USAGE);
+        }
 
-    let manifest_path = get_manifest_path(&relative_manifest_path);
-    let output_path = get_output_path(&relative_output_path);
+        // Let cargo invoke this program as the wrapper around rustc, which summarizes each crate
+        let summary_dir = get_summary_dir(&get_run());
+        std::fs::create_dir_all(&summary_dir).expect("Could not create summary directory!");
 
-    // Extract the compiler arguments from running `cargo build`
-    let compiler_args = get_compiler_args(&relative_manifest_path, &manifest_path)
-        .expect("Could not get arguments from cargo build!");
+        let exit_code = cargo_check_with_wrapper(
+            &manifest_path,
+            &output_path,
+            &args[1..],
+            &arguments,
+            &targets,
+        );
 
-    // Enable CTRL + C
-    rustc_driver::install_ctrlc_handler();
+        println!("Ran cargo, exit code: {exit_code}");
 
-    // Install a panic hook that will print the ICE message on unexpected panics.
-    let using_internal_features =
-        rustc_driver::install_ice_hook(rustc_driver::DEFAULT_BUG_REPORT_URL, |_| ());
+        let summaries = read_summaries(&summary_dir);
+        let _ = std::fs::remove_dir_all(&summary_dir);
 
-    // This allows tools to enable rust logging without having to magically match rustc’s tracing crate version.
-    rustc_driver::init_rustc_env_logger(&early_dcx);
+        if exit_code != 0 {
+            eprintln!("Could not check package!");
+            std::process::exit(EXIT_BUILD_FAILURE);
+        }
+
+        if summaries.is_empty() {
+            eprintln!("No crates were analyzed!");
+            std::process::exit(EXIT_BUILD_FAILURE);
+        }
+
+        // Merge the summaries of all analyzed crates into one graph, after writing the graph of each target if there are several
+        let call_graph = if let [target] = targets.as_slice() {
+            summary::merge(target.crate_name(), &summaries)
+        } else {
+            write_target_outputs(&output_path, &targets, &summaries, &arguments);
+            summary::merge(package.name.replace('-', "_"), &summaries)
+        };
+        write_output(
+            &output_path,
+            &call_graph,
+            arguments.graph == GraphKind::Chains,
+            arguments.format,
+            arguments.locations,
+        );
+        if arguments.swallowed {
+            print_swallowed(&call_graph);
+        }
+
+        std::process::exit(get_exit_code(has_findings(&call_graph)));
+    }
+
+    // Record the compiler invocation from running `cargo build`
+    let Some(invocation) = get_rustc_invocation(&manifest_path, &arguments) else {
+        eprintln!("Could not get arguments from cargo build!");
+        std::process::exit(EXIT_BUILD_FAILURE);
+    };
+
+    // Run the compiler like cargo did, so the (relative) paths in its arguments and e.g. `env!` resolve the same
+    std::env::set_current_dir(&invocation.cwd)
+        .expect("Could not change to the directory rustc was run in!");
+    for (name, value) in &invocation.env {
+        std::env::set_var(name, value);
+    }
 
     // Run the compiler using the retrieved args.
-    let exit_code = run_compiler(
-        compiler_args,
-        &mut AnalysisCallback(output_path, remove_redundant),
-        using_internal_features,
-    );
+    let mut callback = AnalysisCallback {
+        output_path,
+        remove_redundant: arguments.graph == GraphKind::Chains,
+        roots: arguments.roots,
+        summary_path: None,
+        target: None,
+        swallowed: arguments.swallowed,
+        format: arguments.format,
+        locations: arguments.locations,
+        builder: arguments.builder,
+        findings: false,
+    };
+    println!("Running compiler...");
+    let exit_code = run_analysis(&early_dcx, invocation.args, &mut callback);
 
     println!("Ran compiler, exit code: {exit_code}");
+
+    if exit_code != 0 {
+        std::process::exit(EXIT_BUILD_FAILURE);
+    }
+
+    std::process::exit(get_exit_code(callback.findings));
 }
 
-/// Extract the needed arguments from the provided arguments
-fn extract_arguments(args: &[String]) -> (String, String, bool) {
-    if args.len() < 3 {
-        eprintln!("Usage:");
-        eprintln!("static-result-analyzer.exe input output [--call]");
-        eprintln!();
-        eprintln!("Both the input and output path should be relative.");
-        eprintln!(
-            "The call flag will output the call graph instead of the error chain graph if set."
+/// Extract the arguments (without the program name).
+/// Prints the help or version and exits if requested, or prints the problem and exits if the arguments are invalid.
+fn extract_arguments(args: &[String]) -> Arguments {
+    match cli::parse_arguments(args) {
+        Ok(Invocation::Analyze(arguments)) => arguments,
+        Ok(Invocation::Help) => {
+            print!("{USAGE}");
+            std::process::exit(EXIT_SUCCESS);
+        }
+        Ok(Invocation::Version) => {
+            println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            std::process::exit(EXIT_SUCCESS);
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            eprintln!();
+            eprintln!("For more information, try '--help'.");
+            std::process::exit(EXIT_USAGE);
+        }
+    }
+}
+
+/// Get the exit code of a successful analysis, depending on whether it found swallowed errors.
+fn get_exit_code(findings: bool) -> i32 {
+    if findings {
+        EXIT_FINDINGS
+    } else {
+        EXIT_SUCCESS
+    }
+}
+
+/// Merge the summary of each target with those of the crates it uses, and write its graph next to the output file
+/// (e.g. `graph-bin-foo.dot` for `graph.dot`).
+fn write_target_outputs(
+    output_path: &Path,
+    targets: &[Target],
+    summaries: &[CrateSummary],
+    arguments: &Arguments,
+) {
+    let lib = targets
+        .iter()
+        .find(|target| target.kind == TargetKind::Lib)
+        .map(ToString::to_string);
+
+    for target in targets {
+        let id = target.to_string();
+        if !summaries
+            .iter()
+            .any(|summary| summary.target.as_ref() == Some(&id))
+        {
+            eprintln!("Target {id} was not analyzed!");
+            continue;
+        }
+
+        // The summaries of the other targets are left out, apart from the library of the package
+        let target_summaries: Vec<CrateSummary> = summaries
+            .iter()
+            .filter(|summary| match &summary.target {
+                Some(summary_target) => {
+                    *summary_target == id
+                        || (target.uses_lib() && Some(summary_target) == lib.as_ref())
+                }
+                None => true,
+            })
+            .cloned()
+            .collect();
+
+        let call_graph = summary::merge(target.crate_name(), &target_summaries);
+        write_output(
+            &get_target_output_path(output_path, &id),
+            &call_graph,
+            arguments.graph == GraphKind::Chains,
+            arguments.format,
+            arguments.locations,
         );
-        std::process::exit(rustc_driver::EXIT_FAILURE);
     }
+}
 
-    (
-        args.get(1).unwrap().clone(),
-        args.get(2).unwrap().clone(),
-        !args.get(3).is_some_and(|arg| arg == "--call"),
+/// Get the path of the output file of a target, which is the output file with the target appended to its name.
+fn get_target_output_path(output_path: &Path, target: &str) -> PathBuf {
+    let stem = output_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+
+    let mut file_name = format!("{stem}-{target}");
+    if let Some(extension) = output_path.extension() {
+        file_name.push('.');
+        file_name.push_str(&extension.to_string_lossy());
+    }
+
+    output_path.with_file_name(file_name)
+}
+
+/// Run `cargo check` on the given manifest, with this program as the workspace wrapper around rustc.
+/// The wrapper then analyzes the selected targets, and passes all other invocations through to rustc.
+fn cargo_check_with_wrapper(
+    manifest_path: &Path,
+    output_path: &Path,
+    args: &[String],
+    arguments: &Arguments,
+    targets: &[Target],
+) -> i32 {
+    println!("Using {}!", cargo_version().trim_end_matches('\n'));
+    println!("Checking package...");
+
+    // Pass the arguments on to the wrapper, overriding the paths with absolute ones as cargo runs it from the workspace root
+    let mut wrapper_args: Vec<String> = args
+        .iter()
+        .take_while(|arg| *arg != "--")
+        .filter(|arg| *arg != "--rebuild")
+        .cloned()
+        .collect();
+    wrapper_args.extend([
+        String::from("--manifest-path"),
+        manifest_path.display().to_string(),
+        String::from("--output"),
+        output_path.display().to_string(),
+    ]);
+
+    let mut check_command = create_cargo_command();
+    check_command.arg("check");
+    check_command.arg("--manifest-path");
+    check_command.arg(manifest_path.as_os_str());
+    if arguments.workspace {
+        check_command.arg("--workspace");
+    }
+    if matches!(arguments.roots, Roots::Tests) {
+        check_command.arg("--tests");
+    }
+    arguments.cargo.add_args(&mut check_command);
+    check_command.env(
+        "RUSTC_WORKSPACE_WRAPPER",
+        std::env::current_exe().expect("Could not get path to the analyzer!"),
+    );
+    check_command.env(ARGS_ENV, wrapper_args.join(ARGS_SEPARATOR));
+    check_command.env(
+        TARGETS_ENV,
+        serde_json::to_string(targets).expect("Could not serialize targets!"),
+    );
+    check_command.env(RUN_ENV, get_run());
+
+    let status = check_command.status().expect("Could not check!");
+
+    status.code().unwrap_or(rustc_driver::EXIT_FAILURE)
+}
+
+/// Run as the workspace wrapper around rustc, where `args[1]` is the path to rustc.
+/// Summarizes the analyzed crates and passes all other invocations through to rustc.
+fn run_wrapper(
+    early_dcx: &rustc_session::EarlyDiagCtxt,
+    args: &[String],
+    arguments: &Arguments,
+) -> i32 {
+    let rustc_args = &args[2..];
+    let targets: Vec<Target> = std::env::var(TARGETS_ENV)
+        .ok()
+        .and_then(|targets| serde_json::from_str(&targets).ok())
+        .expect("Could not get the targets to analyze!");
+
+    let Some((roots, target)) = get_wrapper_roots(rustc_args, arguments, &targets) else {
+        let status = Command::new(&args[1])
+            .args(rustc_args)
+            .status()
+            .expect("Could not run rustc!");
+
+        return status.code().unwrap_or(rustc_driver::EXIT_FAILURE);
+    };
+
+    // Name the summary after the target, or the crate and its kind, as e.g. a library and binary can share their name
+    let summary_name = if let Some(target) = target {
+        format!("target-{target}.json")
+    } else {
+        let crate_name = get_arg_value(rustc_args, "--crate-name").unwrap_or("unknown");
+        let crate_kind = if rustc_args.iter().any(|arg| arg == "--test") {
+            "test"
+        } else {
+            get_arg_value(rustc_args, "--crate-type").unwrap_or("lib")
+        };
+        format!("{crate_name}-{crate_kind}.json")
+    };
+    let summary_path = std::env::var(RUN_ENV)
+        .map(|run| get_summary_dir(&run).join(summary_name))
+        .expect("Could not get the run of the analyzer!");
+
+    // Continue compiling afterwards, as cargo expects the output of rustc
+    run_analysis(
+        early_dcx,
+        args[1..].to_vec(),
+        &mut AnalysisCallback {
+            output_path: get_output_path(&arguments.output_path),
+            remove_redundant: arguments.graph == GraphKind::Chains,
+            roots,
+            summary_path: Some(summary_path),
+            target: target.map(ToString::to_string),
+            swallowed: false,
+            format: arguments.format,
+            locations: arguments.locations,
+            builder: arguments.builder,
+            findings: false,
+        },
     )
 }
 
+/// Get the roots to analyze the crate compiled by the rustc arguments with, along with the selected target it is (if any),
+/// or `None` if it shouldn't be analyzed.
+/// Workspace crates that are only compiled as dependencies are analyzed from their public functions,
+/// so the calls into them can be linked to the graph of the target crate.
+fn get_wrapper_roots<'a>(
+    rustc_args: &[String],
+    arguments: &Arguments,
+    targets: &'a [Target],
+) -> Option<(Roots, Option<&'a Target>)> {
+    let crate_name = get_arg_value(rustc_args, "--crate-name")?;
+    if crate_name.starts_with("build_script_") {
+        return None;
+    }
+
+    // Cargo queries the target information through the wrapper too, which reads the printed output
+    if rustc_args.iter().any(|arg| arg.starts_with("--print")) {
+        return None;
+    }
+
+    if std::env::var_os("CARGO_PRIMARY_PACKAGE").is_none() {
+        return Some((Roots::Public, None));
+    }
+
+    let tests = matches!(arguments.roots, Roots::Tests);
+    let is_test = rustc_args.iter().any(|arg| arg == "--test");
+
+    if let Some(target) = targets
+        .iter()
+        .find(|target| target.is_compiled_by(rustc_args, tests))
+    {
+        return Some((arguments.roots.clone(), Some(target)));
+    }
+
+    // The package's own library is compiled as a dependency of the selected binaries, examples and tests
+    let is_lib = get_arg_value(rustc_args, "--crate-type").is_some_and(|kind| kind != "bin");
+    if !is_test && is_lib && targets.iter().any(Target::uses_lib) {
+        return Some((Roots::Public, None));
+    }
+
+    if arguments.workspace {
+        return if tests && !is_test {
+            Some((Roots::Public, None))
+        } else {
+            Some((arguments.roots.clone(), None))
+        };
+    }
+
+    None
+}
+
+/// Get the identifier of this run, which is unique for each run so cargo doesn't consider the analyzed crates fresh.
+fn get_run() -> String {
+    static RUN: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
+    RUN.get_or_init(|| {
+        std::env::var(RUN_ENV).unwrap_or_else(|_| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("System time is before the unix epoch!")
+                .as_nanos()
+                .to_string()
+        })
+    })
+    .clone()
+}
+
+/// Get the directory the crate summaries of the given run are written to.
+fn get_summary_dir(run: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("static-result-analyzer-{run}"))
+}
+
+/// Read all crate summaries from the given directory.
+fn read_summaries(summary_dir: &Path) -> Vec<CrateSummary> {
+    let Ok(entries) = std::fs::read_dir(summary_dir) else {
+        return vec![];
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let summary = CrateSummary::read(&entry.path());
+            if summary.is_none() {
+                eprintln!("Could not read summary {}!", entry.path().display());
+            }
+            summary
+        })
+        .collect()
+}
+
 /// Get the full path to the manifest.
 fn get_output_path(output_path: &str) -> PathBuf {
     std::env::current_dir().unwrap().join(output_path)
@@ -87,83 +497,33 @@ fn get_manifest_path(cargo_path: &str) -> PathBuf {
     std::env::current_dir().unwrap().join(cargo_path)
 }
 
-/// Get the compiler arguments used to compile the package by first running `cargo clean` and then `cargo build -vv`.
-fn get_compiler_args(relative_manifest_path: &str, manifest_path: &PathBuf) -> Option<Vec<String>> {
+/// Get the rustc invocation of the target by first running `cargo clean` and then `cargo build`,
+/// with this program as the workspace wrapper that records the invocations.
+fn get_rustc_invocation(manifest_path: &PathBuf, arguments: &Arguments) -> Option<RustcInvocation> {
     println!("Using {}!", cargo_version().trim_end_matches('\n'));
 
-    let (package_name, bin_name) = get_package_name(manifest_path);
+    let package = targets::get_package(manifest_path)?;
 
-    cargo_clean(manifest_path, &package_name);
+    // Test functions are only compiled into the test harness
+    let tests = matches!(arguments.roots, Roots::Tests);
 
-    let build_output = cargo_build_verbose(manifest_path);
+    cargo_clean(manifest_path, &package.name);
 
-    let command = get_rustc_invocation(&build_output, &package_name, bin_name)?;
+    let record_dir = get_summary_dir(&get_run());
+    std::fs::create_dir_all(&record_dir).expect("Could not create record directory!");
 
-    Some(split_args(relative_manifest_path, &command))
-}
-
-/// Split up individual arguments from the command.
-fn split_args(relative_manifest_path: &str, command: &str) -> Vec<String> {
-    let mut res = vec![];
-    let mut temp = String::new();
-
-    // Split on ' '
-    for arg in command.split(' ') {
-        let mut arg = arg.to_owned();
-
-        // If this is the path to main.rs, prepend the relative path to the manifest, stripping away Cargo.toml
-        if arg.contains("main.rs") {
-            let mut new_arg = String::from(relative_manifest_path.trim_end_matches("Cargo.toml"));
-            new_arg.push_str(&arg);
-            arg = new_arg;
-        }
+    cargo_build_recording(manifest_path, tests, &arguments.cargo, &record_dir);
 
-        // Leave ' ' when enclosed in '"', removing the enclosing '"'
-        if arg.starts_with('"') && arg.ends_with('"') {
-            temp.push_str(
-                arg.strip_prefix('"')
-                    .expect("Could not remove '\"' from start of string!")
-                    .strip_suffix('"')
-                    .expect("Could not remove '\"' from end of string!"),
-            );
-            res.push(temp);
-            temp = String::new();
-        } else if arg.ends_with('"') {
-            temp.push_str(
-                arg.strip_suffix('"')
-                    .expect("Could not remove '\"' from end of string!"),
-            );
-            res.push(temp);
-            temp = String::new();
-        } else if arg.starts_with('"') {
-            temp.push_str(
-                arg.strip_prefix('"')
-                    .expect("Could not remove '\"' from start of string!"),
-            );
-            temp.push(' ');
-        } else if !temp.is_empty() {
-            temp.push_str(&arg);
-            temp.push(' ');
-        } else {
-            res.push(arg);
-        }
-    }
+    let invocations = read_invocations(&record_dir);
+    let _ = std::fs::remove_dir_all(&record_dir);
 
-    // Overwrite error format args
-    for i in 0..res.len() {
-        if i >= res.len() {
-            break;
-        }
-        res[i] = res[i].replace("\\\"", "\"");
-        if res[i].starts_with("--error-format=") {
-            res[i] = String::from("--error-format=short");
-        }
-        if res[i].starts_with("--json=") {
-            res.remove(i);
-        }
-    }
+    let target = targets::select_targets(&package, &arguments.cargo.target_crate, tests)
+        .into_iter()
+        .next()?;
 
-    res
+    invocations
+        .into_iter()
+        .find(|invocation| target.is_compiled_by(&invocation.args, tests))
 }
 
 /// Run `cargo clean -p PACKAGE`, where the package name is extracted from the given manifest.
@@ -192,38 +552,6 @@ fn cargo_clean(manifest_path: &PathBuf, package_name: &str) -> String {
     stderr
 }
 
-/// Extract the package name from the given manifest.
-fn get_package_name(manifest_path: &PathBuf) -> (String, Option<String>) {
-    let file = std::fs::read(manifest_path).expect("Could not read manifest!");
-    let content = String::from_utf8(file).expect("Invalid UTF8!");
-    let table = content
-        .parse::<Table>()
-        .expect("Could not parse manifest as TOML!");
-    let package_table = table["package"]
-        .as_table()
-        .expect("'package' is not a table!");
-    let package_name = package_table["name"]
-        .as_str()
-        .expect("No name found in package information!")
-        .to_owned();
-    if table.contains_key("bin") {
-        let binary_table = table["bin"]
-            .as_array()
-            .expect("'bin' is not an array!")
-            .get(0)
-            .expect("'bin' contains no values!")
-            .as_table()
-            .expect("'bin' is not a table!");
-        let binary_name = binary_table["name"]
-            .as_str()
-            .expect("No name found in binary information!")
-            .to_owned();
-        return (package_name, Some(binary_name));
-    }
-
-    (package_name, None)
-}
-
 /// Create a new cargo command.
 fn create_cargo_command() -> Command {
     let command = Command::new("cargo");
@@ -245,15 +573,29 @@ fn cargo_version() -> String {
     stdout
 }
 
-/// Run `cargo build -v` on the given manifest.
-fn cargo_build_verbose(manifest_path: &Path) -> String {
-    // TODO: interrupt build as to not compile the program twice
+/// Run `cargo build` on the given manifest with the given cargo options, with this program as the workspace wrapper around rustc
+/// that records the rustc invocations of the package to the given directory. Also builds the test harnesses if `tests` is set.
+fn cargo_build_recording(
+    manifest_path: &Path,
+    tests: bool,
+    cargo: &CargoOptions,
+    record_dir: &Path,
+) {
+    // TODO: interrupt the build once the target is recorded, as it is compiled again to analyze it
     println!("Building package...");
     let mut build_command = create_cargo_command();
     build_command.arg("build");
-    build_command.arg("-v");
     build_command.arg("--manifest-path");
     build_command.arg(manifest_path.as_os_str());
+    if tests {
+        build_command.arg("--tests");
+    }
+    cargo.add_args(&mut build_command);
+    build_command.env(
+        "RUSTC_WORKSPACE_WRAPPER",
+        std::env::current_exe().expect("Could not get path to the analyzer!"),
+    );
+    build_command.env(RECORD_ENV, record_dir.as_os_str());
 
     let output = build_command.output().expect("Could not build!");
 
@@ -270,35 +612,78 @@ fn cargo_build_verbose(manifest_path: &Path) -> String {
         eprintln!();
         eprintln!("Trying to continue...");
     }
-
-    stderr
 }
 
-/// Gets the rustc invocation command from the output of `cargo build -vv`.
-fn get_rustc_invocation(
-    build_output: &str,
-    package_name: &str,
-    bin_name: Option<String>,
-) -> Option<String> {
-    let name = bin_name
-        .unwrap_or(package_name.to_owned())
-        .replace('-', "_");
-    for line in build_output.split('\n') {
-        for part in line.split('`') {
-            for command in part.split("&& ") {
-                if command.contains("rustc")
-                    && command.contains("--crate-type bin")
-                    && !command.contains("build.rs")
-                    && command.contains("main.rs")
-                    && command.contains(&format!("--crate-name {name}"))
-                {
-                    return Some(String::from(command));
-                }
-            }
+/// Run rustc as the workspace wrapper, where `args[1]` is the path to rustc,
+/// recording the invocation to the given directory if it compiles a crate of the analyzed package.
+/// Returns the exit code of rustc.
+fn record_invocation(args: &[String], record_dir: &Path) -> i32 {
+    if std::env::var_os("CARGO_PRIMARY_PACKAGE").is_some() {
+        let invocation = RustcInvocation {
+            args: args[1..].to_vec(),
+            cwd: std::env::current_dir().expect("Could not get the directory rustc is run in!"),
+            env: std::env::vars_os()
+                .filter_map(|(name, value)| {
+                    Some((name.into_string().ok()?, value.into_string().ok()?))
+                })
+                .collect(),
+        };
+        let json = serde_json::to_string(&invocation).expect("Could not serialize invocation!");
+
+        // Every invocation is run by its own wrapper process
+        let path = record_dir.join(format!("{}.json", std::process::id()));
+        if let Err(e) = std::fs::write(path, json) {
+            eprintln!("Could not record rustc invocation!");
+            eprintln!("{e}");
         }
     }
 
-    None
+    let status = Command::new(&args[1])
+        .args(&args[2..])
+        .status()
+        .expect("Could not run rustc!");
+
+    status.code().unwrap_or(rustc_driver::EXIT_FAILURE)
+}
+
+/// Read all recorded rustc invocations from the given directory.
+fn read_invocations(record_dir: &Path) -> Vec<RustcInvocation> {
+    let Ok(entries) = std::fs::read_dir(record_dir) else {
+        return vec![];
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let invocation = std::fs::read_to_string(entry.path())
+                .ok()
+                .and_then(|json| serde_json::from_str(&json).ok());
+            if invocation.is_none() {
+                eprintln!("Could not read invocation {}!", entry.path().display());
+            }
+            invocation
+        })
+        .collect()
+}
+
+/// Set up the compiler, and run it with the provided arguments and analysis callback.
+/// Returns the exit code of the compiler.
+fn run_analysis(
+    early_dcx: &rustc_session::EarlyDiagCtxt,
+    compiler_args: Vec<String>,
+    callback: &mut AnalysisCallback,
+) -> i32 {
+    // Enable CTRL + C
+    rustc_driver::install_ctrlc_handler();
+
+    // Install a panic hook that will print the ICE message on unexpected panics.
+    let using_internal_features =
+        rustc_driver::install_ice_hook(rustc_driver::DEFAULT_BUG_REPORT_URL, |_| ());
+
+    // This allows tools to enable rust logging without having to magically match rustc’s tracing crate version.
+    rustc_driver::init_rustc_env_logger(early_dcx);
+
+    run_compiler(compiler_args, callback, using_internal_features)
 }
 
 /// Run a compiler with the provided arguments and callbacks.
@@ -308,8 +693,6 @@ fn run_compiler(
     callbacks: &mut (dyn rustc_driver::Callbacks + Send),
     using_internal_features: std::sync::Arc<std::sync::atomic::AtomicBool>,
 ) -> i32 {
-    println!("Running compiler...");
-
     // Invoke compiler, and return the exit code
     rustc_driver::catch_with_exit_code(move || {
         rustc_driver::RunCompiler::new(&args, callbacks)
@@ -318,9 +701,103 @@ fn run_compiler(
     })
 }
 
-struct AnalysisCallback(PathBuf, bool);
+/// Write the chain graph (or the call graph if `remove_redundant` is not set) to the output file.
+/// The JSON format always contains the call graph, along with the chains within it,
+/// and the SARIF format contains the findings of the analysis.
+fn write_output(
+    output_path: &Path,
+    call_graph: &CallGraph,
+    remove_redundant: bool,
+    format: Format,
+    locations: DotLocations,
+) {
+    let output = match format {
+        Format::Dot if remove_redundant => analysis::chains(call_graph).to_dot(locations),
+        Format::Dot => call_graph.to_dot(locations),
+        Format::Json => json::to_json(call_graph, &analysis::chains(call_graph)),
+        Format::Sarif => sarif::to_sarif(call_graph, &analysis::chains(call_graph)),
+    };
+
+    println!("Writing graph...");
+
+    match std::fs::write(output_path, &output) {
+        Ok(()) => {
+            println!("Done!");
+            println!("Wrote to {}", output_path.display());
+        }
+        Err(e) => {
+            eprintln!("Could not write output!");
+            eprintln!("{e}");
+            eprintln!();
+            println!("{output}");
+        }
+    }
+}
+
+/// Print every call site where an error is swallowed, along with what happens to it.
+fn print_swallowed(call_graph: &CallGraph) {
+    println!();
+    println!("Swallowed errors:");
+
+    for edge in &call_graph.edges {
+        let Some(sink) = edge.sink.filter(|sink| sink.is_swallowed()) else {
+            continue;
+        };
+
+        let location = edge
+            .location
+            .as_ref()
+            .map_or(String::from("unknown location"), ToString::to_string);
+
+        println!(
+            "{location}: {} returned by {} is swallowed ({sink}) in {}",
+            edge.ty.clone().unwrap_or(String::from("unknown")),
+            call_graph.nodes[edge.to].label,
+            call_graph.nodes[edge.from].label,
+        );
+    }
+}
+
+/// Check whether an error is swallowed anywhere in the call graph.
+fn has_findings(call_graph: &CallGraph) -> bool {
+    call_graph
+        .edges
+        .iter()
+        .any(|edge| edge.sink.is_some_and(|sink| sink.is_swallowed()))
+}
+
+struct AnalysisCallback {
+    output_path: PathBuf,
+    remove_redundant: bool,
+    roots: Roots,
+    /// Where to write the summary of the crate to instead of the output, when running as a wrapper.
+    summary_path: Option<PathBuf>,
+    /// The selected target the crate is (e.g. `bin-foo`), if any.
+    target: Option<String>,
+    swallowed: bool,
+    format: Format,
+    locations: DotLocations,
+    builder: Builder,
+    /// Whether the analysis found swallowed errors.
+    findings: bool,
+}
 
 impl rustc_driver::Callbacks for AnalysisCallback {
+    fn config(&mut self, config: &mut rustc_interface::Config) {
+        if self.summary_path.is_none() {
+            return;
+        }
+
+        // Track the run in the dep-info, so cargo will invoke the wrapper on this crate again next run
+        let run = std::env::var(RUN_ENV).ok();
+        config.psess_created = Some(Box::new(move |psess| {
+            psess
+                .env_depinfo
+                .get_mut()
+                .insert((Symbol::intern(RUN_ENV), run.as_deref().map(Symbol::intern)));
+        }));
+    }
+
     fn after_crate_root_parsing<'tcx>(
         &mut self,
         _compiler: &Compiler,
@@ -328,33 +805,42 @@ impl rustc_driver::Callbacks for AnalysisCallback {
     ) -> Compilation {
         // Access type context
         queries.global_ctxt().unwrap().enter(|context| {
-            println!("Analyzing output...");
-            // Analyze the program using the type context
-            let (call_graph, chain_graph) = analysis::analyze(context);
-
-            let dot = if self.1 {
-                chain_graph.to_dot()
-            } else {
-                call_graph.to_dot()
-            };
-
-            println!("Writing graph...");
+            // Every crate checked by cargo is analyzed by its own wrapper process, which only reports problems
+            if self.summary_path.is_none() {
+                println!("Analyzing output...");
+            }
 
-            match std::fs::write(&self.0, dot.clone()) {
-                Ok(()) => {
-                    println!("Done!");
-                    println!("Wrote to {}", &self.0.display());
-                }
-                Err(e) => {
-                    eprintln!("Could not write output!");
+            // Analyze the program using the type context
+            let call_graph = analysis::analyze(context, &self.roots, self.builder);
+
+            if let Some(summary_path) = &self.summary_path {
+                // The summaries are merged and written to the output once all crates are analyzed
+                let mut summary = CrateSummary::new(context, &call_graph);
+                summary.target.clone_from(&self.target);
+                if let Err(e) = summary.write(summary_path) {
+                    eprintln!("Could not write summary!");
                     eprintln!("{e}");
-                    eprintln!();
-                    println!("{dot}");
+                }
+            } else {
+                write_output(
+                    &self.output_path,
+                    &call_graph,
+                    self.remove_redundant,
+                    self.format,
+                    self.locations,
+                );
+                self.findings = has_findings(&call_graph);
+                if self.swallowed {
+                    print_swallowed(&call_graph);
                 }
             }
         });
 
-        // No need to compile further
-        Compilation::Stop
+        // No need to compile further, unless cargo expects the output
+        if self.summary_path.is_some() {
+            Compilation::Continue
+        } else {
+            Compilation::Stop
+        }
     }
 }
@@ -0,0 +1,334 @@
+use crate::graph::{CallEdge, CallGraph, Chain, ChainGraph, ChainKind, Location};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// The version of SARIF the report conforms to.
+const SARIF_VERSION: &str = "2.1.0";
+
+/// The schema of the SARIF version the report conforms to.
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// The rules of the findings that can be reported, with their description.
+const RULES: [(&str, &str); 4] = [
+    (
+        ERROR_CHAIN_RULE,
+        "An error is propagated through a chain of function calls before it is handled.",
+    ),
+    (
+        NONE_CHAIN_RULE,
+        "A `None` is propagated through a chain of function calls before it is handled.",
+    ),
+    (
+        SWALLOWED_ERROR_RULE,
+        "An error is swallowed instead of being handled or propagated.",
+    ),
+    (PANIC_RULE, "A function can panic."),
+];
+
+const ERROR_CHAIN_RULE: &str = "error-propagation-chain";
+const NONE_CHAIN_RULE: &str = "none-propagation-chain";
+const SWALLOWED_ERROR_RULE: &str = "swallowed-error";
+const PANIC_RULE: &str = "panicking-function";
+
+/// The maximum number of thread flows in the code flow of a chain.
+const MAX_THREAD_FLOWS: usize = 16;
+
+#[derive(Serialize)]
+struct SarifLog {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<SarifRun>,
+}
+
+#[derive(Serialize)]
+struct SarifRun {
+    tool: SarifTool,
+    results: Vec<SarifResult>,
+}
+
+#[derive(Serialize)]
+struct SarifTool {
+    driver: SarifDriver,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifDriver {
+    name: &'static str,
+    version: &'static str,
+    rules: Vec<SarifRule>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifRule {
+    id: &'static str,
+    short_description: SarifMessage,
+}
+
+#[derive(Serialize)]
+struct SarifMessage {
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: &'static str,
+    level: &'static str,
+    message: SarifMessage,
+    locations: Vec<SarifLocation>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    code_flows: Vec<SarifCodeFlow>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifLocation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    physical_location: Option<SarifPhysicalLocation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<SarifMessage>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifPhysicalLocation {
+    artifact_location: SarifArtifactLocation,
+    region: SarifRegion,
+}
+
+#[derive(Serialize)]
+struct SarifArtifactLocation {
+    uri: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifRegion {
+    start_line: usize,
+    start_column: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifCodeFlow {
+    thread_flows: Vec<SarifThreadFlow>,
+}
+
+#[derive(Serialize)]
+struct SarifThreadFlow {
+    locations: Vec<SarifThreadFlowLocation>,
+}
+
+#[derive(Serialize)]
+struct SarifThreadFlowLocation {
+    location: SarifLocation,
+}
+
+/// Convert the findings in the call graph to a SARIF report: the starts of the chains,
+/// the swallowed errors and the panicking functions.
+pub fn to_sarif(call_graph: &CallGraph, chain_graph: &ChainGraph) -> String {
+    let mut results = vec![];
+
+    // The start of a chain is the last edge of it, where the propagated value is handled
+    for chain in &chain_graph.chains {
+        let Some(start) = chain.edges.last().map(|id| &call_graph.edges[*id]) else {
+            continue;
+        };
+
+        let (rule_id, value) = match chain.kind {
+            ChainKind::Result => (ERROR_CHAIN_RULE, "An error"),
+            ChainKind::Option => (NONE_CHAIN_RULE, "A `None`"),
+        };
+
+        results.push(SarifResult {
+            rule_id,
+            level: "note",
+            message: SarifMessage {
+                text: format!(
+                    "{value} of type {} returned by {} is propagated through {} calls before it is handled in {}.",
+                    start.ty.clone().unwrap_or(String::from("unknown")),
+                    call_graph.nodes[start.to].label,
+                    chain.edges.len(),
+                    call_graph.nodes[start.from].label,
+                ),
+            },
+            locations: vec![get_location(start.location.as_ref(), None)],
+            code_flows: vec![get_code_flow(call_graph, chain)],
+        });
+    }
+
+    for edge in &call_graph.edges {
+        let Some(sink) = edge.sink.filter(|sink| sink.is_swallowed()) else {
+            continue;
+        };
+
+        // A swallowed error is the start of a chain if it is propagated to the call
+        let chain = chain_graph
+            .chains
+            .iter()
+            .find(|chain| chain.edges.last() == Some(&edge.id()));
+
+        results.push(SarifResult {
+            rule_id: SWALLOWED_ERROR_RULE,
+            level: "warning",
+            message: SarifMessage {
+                text: format!(
+                    "{} returned by {} is swallowed ({sink}) in {}.",
+                    edge.ty.clone().unwrap_or(String::from("unknown")),
+                    call_graph.nodes[edge.to].label,
+                    call_graph.nodes[edge.from].label,
+                ),
+            },
+            locations: vec![get_location(edge.location.as_ref(), None)],
+            code_flows: chain
+                .map(|chain| get_code_flow(call_graph, chain))
+                .into_iter()
+                .collect(),
+        });
+    }
+
+    for node in &call_graph.nodes {
+        if !node.panics {
+            continue;
+        }
+
+        let sources: Vec<String> = node.panic_sources.iter().map(ToString::to_string).collect();
+
+        results.push(SarifResult {
+            rule_id: PANIC_RULE,
+            level: "warning",
+            message: SarifMessage {
+                text: format!("{} can panic: {}.", node.label, sources.join(", ")),
+            },
+            locations: vec![get_location(node.location.as_ref(), None)],
+            code_flows: vec![],
+        });
+    }
+
+    let log = SarifLog {
+        schema: SARIF_SCHEMA,
+        version: SARIF_VERSION,
+        runs: vec![SarifRun {
+            tool: SarifTool {
+                driver: SarifDriver {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                    rules: RULES
+                        .iter()
+                        .map(|(id, description)| SarifRule {
+                            id,
+                            short_description: SarifMessage {
+                                text: String::from(*description),
+                            },
+                        })
+                        .collect(),
+                },
+            },
+            results,
+        }],
+    };
+
+    serde_json::to_string_pretty(&log).expect("Could not serialize SARIF report!")
+}
+
+/// Get the code flow of a chain, with a thread flow for each path from an origin of the
+/// propagated value to where it is handled.
+fn get_code_flow(call_graph: &CallGraph, chain: &Chain) -> SarifCodeFlow {
+    let edges: Vec<&CallEdge> = chain
+        .edges
+        .iter()
+        .map(|id| &call_graph.edges[*id])
+        .collect();
+
+    SarifCodeFlow {
+        thread_flows: get_paths(&edges)
+            .into_iter()
+            .map(|path| SarifThreadFlow {
+                locations: path
+                    .iter()
+                    .map(|edge| SarifThreadFlowLocation {
+                        location: get_location(
+                            edge.location.as_ref(),
+                            Some(format!(
+                                "{} returns {} to {}",
+                                call_graph.nodes[edge.to].label,
+                                edge.label(),
+                                call_graph.nodes[edge.from].label,
+                            )),
+                        ),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+/// Find the paths through the edges of a chain, from its origins to its start (the last edge).
+/// Listing every path is exponential in the calls that fan in, so this walks a spanning tree of the chain instead,
+/// which reaches each function once, and returns the path to each of its leaves (up to `MAX_THREAD_FLOWS`).
+fn get_paths<'a>(edges: &[&'a CallEdge]) -> Vec<Vec<&'a CallEdge>> {
+    let Some(start) = edges.len().checked_sub(1) else {
+        return vec![];
+    };
+
+    // The propagating edges out of each function
+    let mut outgoing: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (index, edge) in edges.iter().enumerate() {
+        if edge.propagates {
+            outgoing.entry(edge.from).or_default().push(index);
+        }
+    }
+
+    let mut visited = HashSet::from([edges[start].from, edges[start].to]);
+    let mut parents: HashMap<usize, usize> = HashMap::new();
+    let mut leaves = vec![];
+    let mut stack = vec![start];
+    while let Some(index) = stack.pop() {
+        let mut is_leaf = true;
+        for next in outgoing.get(&edges[index].to).into_iter().flatten() {
+            if visited.insert(edges[*next].to) {
+                is_leaf = false;
+                parents.insert(*next, index);
+                stack.push(*next);
+            }
+        }
+
+        if is_leaf {
+            leaves.push(index);
+        }
+    }
+
+    // Following the parents from a leaf gives the path from the origin to the start
+    leaves
+        .into_iter()
+        .take(MAX_THREAD_FLOWS)
+        .map(|leaf| {
+            let mut path = vec![edges[leaf]];
+            let mut index = leaf;
+            while let Some(parent) = parents.get(&index) {
+                path.push(edges[*parent]);
+                index = *parent;
+            }
+            path
+        })
+        .collect()
+}
+
+/// Get a SARIF location from a location in the source code, if it is known.
+fn get_location(location: Option<&Location>, message: Option<String>) -> SarifLocation {
+    SarifLocation {
+        physical_location: location.map(|location| SarifPhysicalLocation {
+            artifact_location: SarifArtifactLocation {
+                uri: location.file.replace('\\', "/"),
+            },
+            region: SarifRegion {
+                start_line: location.line,
+                start_column: location.column,
+            },
+        }),
+        message: message.map(|text| SarifMessage { text }),
+    }
+}
@@ -0,0 +1,229 @@
+use crate::analysis;
+use crate::graph::{
+    CallEdge, CallGraph, CallNodeKind, Combinator, ErrorConversion, ErrorSink, ErrorType, Location,
+    PanicSource, TryKind,
+};
+use rustc_hir::def_id::DefId;
+use rustc_middle::ty::TyCtxt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Summary of the call graph of a single crate, which can be merged with the summaries of other crates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateSummary {
+    pub crate_name: String,
+    /// The selected target the crate was analyzed as (e.g. `bin-foo`), if any.
+    #[serde(default)]
+    pub target: Option<String>,
+    pub nodes: Vec<NodeSummary>,
+    pub edges: Vec<EdgeSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeSummary {
+    /// The stable `DefPathHash` of the function, which is the same in every crate.
+    pub hash: String,
+    pub label: String,
+    pub krate: String,
+    /// Whether the function is defined in the summarized crate.
+    pub local: bool,
+    pub panics: bool,
+    pub panic_sources: Vec<PanicSource>,
+    pub location: Option<Location>,
+    pub trait_dispatch: bool,
+    pub origin: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EdgeSummary {
+    pub from: String,
+    pub to: String,
+    pub ty: Option<String>,
+    pub propagates: bool,
+    pub is_error: bool,
+    pub error: Option<ErrorType>,
+    pub alias: Option<String>,
+    pub try_kind: Option<TryKind>,
+    pub conversion: Option<ErrorConversion>,
+    #[serde(default)]
+    pub from_impl: Option<String>,
+    pub combinators: Vec<Combinator>,
+    pub wrapped: Option<bool>,
+    pub sink: Option<ErrorSink>,
+    pub location: Option<Location>,
+    pub may_call: bool,
+}
+
+impl CrateSummary {
+    /// Summarize the call graph of the current crate.
+    pub fn new(context: TyCtxt, graph: &CallGraph) -> Self {
+        let hashes: Vec<String> = graph
+            .nodes
+            .iter()
+            .map(|node| {
+                // Calls through dispatch are kept apart from the (default) implementation of the trait method
+                let hash = get_hash(context, node.kind.def_id());
+                if node.trait_dispatch {
+                    format!("dyn-{hash}")
+                } else if let CallNodeKind::ErrorOrigin(_def_id, index) = node.kind {
+                    format!("{hash}-origin-{index}")
+                } else {
+                    hash
+                }
+            })
+            .collect();
+
+        let nodes = graph
+            .nodes
+            .iter()
+            .zip(&hashes)
+            .map(|(node, hash)| NodeSummary {
+                hash: hash.clone(),
+                label: node.label.clone(),
+                krate: node.krate.clone(),
+                local: matches!(
+                    node.kind,
+                    CallNodeKind::LocalFn(_, _) | CallNodeKind::ErrorOrigin(_, _)
+                ),
+                panics: node.panics,
+                panic_sources: node.panic_sources.clone(),
+                location: node.location.clone(),
+                trait_dispatch: node.trait_dispatch,
+                origin: node.origin,
+            })
+            .collect();
+
+        let edges = graph
+            .edges
+            .iter()
+            .map(|edge| EdgeSummary {
+                from: hashes[edge.from].clone(),
+                to: hashes[edge.to].clone(),
+                ty: edge.ty.clone(),
+                propagates: edge.propagates,
+                is_error: edge.is_error,
+                error: edge.error.clone(),
+                alias: edge.alias.clone(),
+                try_kind: edge.try_kind.clone(),
+                conversion: edge.conversion.clone(),
+                from_impl: edge.from_impl.clone(),
+                combinators: edge.combinators.clone(),
+                wrapped: edge.wrapped,
+                sink: edge.sink,
+                location: edge.location.clone(),
+                may_call: edge.may_call,
+            })
+            .collect();
+
+        CrateSummary {
+            crate_name: graph.crate_name.clone(),
+            target: None,
+            nodes,
+            edges,
+        }
+    }
+
+    /// Read a summary from the given file.
+    pub fn read(path: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Write this summary to the given file.
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)
+    }
+}
+
+/// Merge the summaries of several crates into a single call graph, linking calls between the crates.
+/// Functions of crates other than the named one are prefixed by their crate name.
+pub fn merge(crate_name: String, summaries: &[CrateSummary]) -> CallGraph {
+    let mut graph = CallGraph::new(crate_name);
+    let mut node_map: HashMap<&str, usize> = HashMap::new();
+
+    // Add the nodes of functions defined in the summarized crates first, so they are labelled by their own crate
+    for summary in summaries {
+        for node in summary.nodes.iter().filter(|node| node.local) {
+            if node_map.contains_key(node.hash.as_str()) {
+                continue;
+            }
+
+            let label = if summary.crate_name == graph.crate_name {
+                node.label.clone()
+            } else {
+                format!("{}::{}", node.krate, node.label)
+            };
+            let id = graph.add_node(&label, CallNodeKind::summarized(node.hash.clone()));
+            node_map.insert(&node.hash, id);
+        }
+    }
+
+    // Then add the remaining nodes, which are functions from outside the summarized crates
+    for summary in summaries {
+        for node in &summary.nodes {
+            let id = if let Some(id) = node_map.get(node.hash.as_str()) {
+                *id
+            } else {
+                let id = graph.add_node(&node.label, CallNodeKind::summarized(node.hash.clone()));
+                node_map.insert(&node.hash, id);
+                id
+            };
+
+            let merged = &mut graph.nodes[id];
+            merged.krate.clone_from(&node.krate);
+            merged.location.clone_from(&node.location);
+            merged.trait_dispatch = node.trait_dispatch;
+            merged.origin = node.origin;
+            merged.panics |= node.panics;
+            for source in &node.panic_sources {
+                if !merged.panic_sources.contains(source) {
+                    merged.panic_sources.push(source.clone());
+                }
+            }
+        }
+    }
+
+    // Add all edges, which now link the calls into other crates with the functions in those crates
+    for summary in summaries {
+        for edge in &summary.edges {
+            // A stale or partial summary may refer to a function that no summary contains
+            let (Some(from), Some(to)) = (
+                node_map.get(edge.from.as_str()),
+                node_map.get(edge.to.as_str()),
+            ) else {
+                eprintln!(
+                    "Skipping call from {} to {} in {}, as the function is not summarized!",
+                    edge.from, edge.to, summary.crate_name
+                );
+                continue;
+            };
+
+            let mut call_edge = CallEdge::new(*from, *to, None, edge.propagates);
+            call_edge.ty.clone_from(&edge.ty);
+            call_edge.is_error = edge.is_error;
+            call_edge.error.clone_from(&edge.error);
+            call_edge.alias.clone_from(&edge.alias);
+            call_edge.try_kind.clone_from(&edge.try_kind);
+            call_edge.conversion.clone_from(&edge.conversion);
+            call_edge.from_impl.clone_from(&edge.from_impl);
+            call_edge.combinators.clone_from(&edge.combinators);
+            call_edge.wrapped = edge.wrapped;
+            call_edge.sink = edge.sink;
+            call_edge.location.clone_from(&edge.location);
+            call_edge.may_call = edge.may_call;
+            graph.add_edge(call_edge);
+        }
+    }
+
+    // Functions that call a panicking function in another crate only panic once the crates are linked
+    analysis::propagate_panics(&mut graph);
+
+    graph
+}
+
+/// Get the stable `DefPathHash` of a function (or other item) as a string.
+pub fn get_hash(context: TyCtxt, def_id: DefId) -> String {
+    let (high, low) = context.def_path_hash(def_id).0.split();
+    format!("{high:016x}{low:016x}")
+}
@@ -0,0 +1,353 @@
+use crate::cli::TargetCrate;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A package, as reported by `cargo metadata`.
+#[derive(Debug, Clone)]
+pub struct Package {
+    pub name: String,
+    pub targets: Vec<Target>,
+}
+
+/// A target of a package (e.g. a binary or an integration test), as reported by `cargo metadata`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Target {
+    pub name: String,
+    pub kind: TargetKind,
+    /// The crate type the target is compiled to (e.g. `bin`, `lib` or `proc-macro`).
+    pub crate_type: String,
+    /// The path to the crate root of the target.
+    pub src_path: PathBuf,
+}
+
+/// The kind of a target, where every kind of library (e.g. `proc-macro`) is a `Lib`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TargetKind {
+    Lib,
+    Bin,
+    Example,
+    Test,
+    Bench,
+}
+
+/// The output of `cargo metadata`, of which only the targets of the packages are used.
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<MetadataPackage>,
+}
+
+#[derive(Deserialize)]
+struct MetadataPackage {
+    name: String,
+    manifest_path: PathBuf,
+    targets: Vec<MetadataTarget>,
+}
+
+#[derive(Deserialize)]
+struct MetadataTarget {
+    name: String,
+    kind: Vec<String>,
+    crate_types: Vec<String>,
+    src_path: PathBuf,
+}
+
+impl Target {
+    /// Get the name of the crate of this target, as passed to rustc.
+    pub fn crate_name(&self) -> String {
+        self.name.replace('-', "_")
+    }
+
+    /// Get the kind of crate (`bin`, `lib` or `test`) this target is compiled to when it is analyzed.
+    /// Integration tests and benches are always compiled into a test harness, the other targets only if `tests` is set.
+    pub fn crate_kind(&self, tests: bool) -> &str {
+        match self.kind {
+            TargetKind::Test | TargetKind::Bench => "test",
+            _ if tests => "test",
+            TargetKind::Lib | TargetKind::Bin | TargetKind::Example => &self.crate_type,
+        }
+    }
+
+    /// Check whether the rustc arguments compile this target.
+    pub fn is_compiled_by(&self, rustc_args: &[String], tests: bool) -> bool {
+        if get_arg_value(rustc_args, "--crate-name") != Some(&self.crate_name()) {
+            return false;
+        }
+
+        let kind = self.crate_kind(tests);
+        let is_kind = if kind == "test" {
+            rustc_args.iter().any(|arg| arg == "--test")
+        } else {
+            get_arg_value(rustc_args, "--crate-type") == Some(kind)
+        };
+
+        // The crate root is passed relative to the workspace root, so e.g. a binary and library of the same name can be told apart
+        is_kind
+            && rustc_args
+                .iter()
+                .any(|arg| arg.ends_with(".rs") && self.src_path.ends_with(arg))
+    }
+
+    /// Check whether this target depends on the library of its package, if it has one.
+    pub fn uses_lib(&self) -> bool {
+        self.kind != TargetKind::Lib
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            TargetKind::Lib => "lib",
+            TargetKind::Bin => "bin",
+            TargetKind::Example => "example",
+            TargetKind::Test => "test",
+            TargetKind::Bench => "bench",
+        };
+
+        write!(f, "{kind}-{}", self.name)
+    }
+}
+
+/// Get the package with the given manifest, along with its targets, using `cargo metadata`.
+/// Prints the problem and returns `None` if the metadata could not be retrieved.
+pub fn get_package(manifest_path: &Path) -> Option<Package> {
+    let output = match Command::new("cargo")
+        .arg("metadata")
+        .arg("--format-version")
+        .arg("1")
+        .arg("--no-deps")
+        .arg("--manifest-path")
+        .arg(manifest_path.as_os_str())
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("Could not run cargo metadata!");
+            eprintln!("{e}");
+            return None;
+        }
+    };
+
+    if !output.status.success() {
+        eprintln!("Could not get package metadata!");
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+        return None;
+    }
+
+    let metadata: Metadata = match serde_json::from_slice(&output.stdout) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            eprintln!("Could not parse package metadata!");
+            eprintln!("{e}");
+            return None;
+        }
+    };
+
+    // The metadata contains every package in the workspace, of which the one with the given manifest is analyzed
+    let Ok(manifest_path) = manifest_path.canonicalize() else {
+        eprintln!("Could not find manifest {}!", manifest_path.display());
+        return None;
+    };
+    let Some(package) = metadata
+        .packages
+        .into_iter()
+        .find(|package| package.manifest_path.canonicalize().ok().as_ref() == Some(&manifest_path))
+    else {
+        eprintln!("The manifest contains no package!");
+        return None;
+    };
+
+    let targets = package
+        .targets
+        .into_iter()
+        .filter_map(|target| {
+            let kind = match target.kind.first().map(String::as_str) {
+                Some("bin") => TargetKind::Bin,
+                Some("example") => TargetKind::Example,
+                Some("test") => TargetKind::Test,
+                Some("bench") => TargetKind::Bench,
+                // Build scripts are not analyzed
+                Some("custom-build") | None => return None,
+                Some(_) => TargetKind::Lib,
+            };
+
+            Some(Target {
+                name: target.name,
+                kind,
+                crate_type: target.crate_types.into_iter().next()?,
+                src_path: target.src_path,
+            })
+        })
+        .collect();
+
+    Some(Package {
+        name: package.name,
+        targets,
+    })
+}
+
+/// Select the targets to analyze from the targets of a package.
+/// Unless a target is selected, prefers the binary over the library, or the library's tests over the binary's if `tests` is set.
+/// Of multiple binaries, the one named after the package is preferred.
+pub fn select_targets(package: &Package, selection: &TargetCrate, tests: bool) -> Vec<Target> {
+    let find = |kind: TargetKind, name: Option<&str>| {
+        package
+            .targets
+            .iter()
+            .filter(|target| {
+                target.kind == kind && (name.is_none() || name == Some(target.name.as_str()))
+            })
+            .cloned()
+            .collect()
+    };
+
+    match selection {
+        TargetCrate::Main => {
+            let lib = package
+                .targets
+                .iter()
+                .find(|target| target.kind == TargetKind::Lib);
+            let bin = package
+                .targets
+                .iter()
+                .find(|target| target.kind == TargetKind::Bin && target.name == package.name)
+                .or_else(|| {
+                    package
+                        .targets
+                        .iter()
+                        .find(|target| target.kind == TargetKind::Bin)
+                });
+
+            let target = if tests { lib.or(bin) } else { bin.or(lib) };
+            target.into_iter().cloned().collect()
+        }
+        TargetCrate::Lib => find(TargetKind::Lib, None),
+        TargetCrate::Bin(name) => find(TargetKind::Bin, Some(name)),
+        TargetCrate::Example(name) => find(TargetKind::Example, Some(name)),
+        TargetCrate::Test(name) => find(TargetKind::Test, Some(name)),
+        TargetCrate::Bench(name) => find(TargetKind::Bench, Some(name)),
+        TargetCrate::All => package.targets.clone(),
+    }
+}
+
+/// Get the value following the given option in the rustc arguments.
+pub fn get_arg_value<'a>(rustc_args: &'a [String], name: &str) -> Option<&'a str> {
+    rustc_args
+        .iter()
+        .position(|arg| arg == name)
+        .and_then(|i| rustc_args.get(i + 1))
+        .map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(name: &str, kind: TargetKind, crate_type: &str, src_path: &str) -> Target {
+        Target {
+            name: String::from(name),
+            kind,
+            crate_type: String::from(crate_type),
+            src_path: PathBuf::from("/work/foo").join(src_path),
+        }
+    }
+
+    /// A package with a library and a binary of the same name, and another binary.
+    fn package() -> Package {
+        Package {
+            name: String::from("foo"),
+            targets: vec![
+                target("foo", TargetKind::Lib, "lib", "src/lib.rs"),
+                target("bar", TargetKind::Bin, "bin", "src/bin/bar.rs"),
+                target("foo", TargetKind::Bin, "bin", "src/main.rs"),
+                target("it", TargetKind::Test, "bin", "tests/it.rs"),
+            ],
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    fn names(targets: &[Target]) -> Vec<String> {
+        targets.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn select_main_target() {
+        let package = package();
+        assert_eq!(
+            names(&select_targets(&package, &TargetCrate::Main, false)),
+            ["bin-foo"]
+        );
+        assert_eq!(
+            names(&select_targets(&package, &TargetCrate::Main, true)),
+            ["lib-foo"]
+        );
+
+        let lib_only = Package {
+            name: String::from("foo"),
+            targets: vec![target("foo", TargetKind::Lib, "lib", "src/lib.rs")],
+        };
+        assert_eq!(
+            names(&select_targets(&lib_only, &TargetCrate::Main, false)),
+            ["lib-foo"]
+        );
+    }
+
+    #[test]
+    fn select_named_target() {
+        let package = package();
+        let bin = TargetCrate::Bin(String::from("bar"));
+        assert_eq!(names(&select_targets(&package, &bin, false)), ["bin-bar"]);
+        assert_eq!(
+            names(&select_targets(&package, &TargetCrate::Lib, false)),
+            ["lib-foo"]
+        );
+
+        let missing = TargetCrate::Example(String::from("bar"));
+        assert!(select_targets(&package, &missing, false).is_empty());
+        assert_eq!(
+            select_targets(&package, &TargetCrate::All, false).len(),
+            package.targets.len()
+        );
+    }
+
+    #[test]
+    fn bin_and_lib_with_same_crate_name() {
+        let package = package();
+        let lib = &package.targets[0];
+        let bin = &package.targets[2];
+        let lib_args = args(&["--crate-name", "foo", "--crate-type", "lib", "src/lib.rs"]);
+        let bin_args = args(&["--crate-name", "foo", "--crate-type", "bin", "src/main.rs"]);
+
+        assert!(lib.is_compiled_by(&lib_args, false));
+        assert!(!lib.is_compiled_by(&bin_args, false));
+        assert!(bin.is_compiled_by(&bin_args, false));
+        assert!(!bin.is_compiled_by(&lib_args, false));
+    }
+
+    #[test]
+    fn compiled_as_test() {
+        let package = package();
+        let lib = &package.targets[0];
+        let test = &package.targets[3];
+        let lib_args = args(&["--crate-name", "foo", "--crate-type", "lib", "src/lib.rs"]);
+        let lib_test_args = args(&["--crate-name", "foo", "--test", "src/lib.rs"]);
+        let test_args = args(&["--crate-name", "it", "--test", "tests/it.rs"]);
+
+        assert!(lib.is_compiled_by(&lib_test_args, true));
+        assert!(!lib.is_compiled_by(&lib_args, true));
+        assert!(!lib.is_compiled_by(&lib_test_args, false));
+        assert!(test.is_compiled_by(&test_args, false));
+    }
+
+    #[test]
+    fn arg_value() {
+        let rustc_args = args(&["--edition=2021", "--crate-name", "foo", "--crate-type"]);
+        assert_eq!(get_arg_value(&rustc_args, "--crate-name"), Some("foo"));
+        assert_eq!(get_arg_value(&rustc_args, "--crate-type"), None);
+        assert_eq!(get_arg_value(&rustc_args, "--edition"), None);
+    }
+}
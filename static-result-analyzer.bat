@@ -63,7 +63,7 @@ if %should_call% == n ( goto after_call )
 
 
 :set_call
-set call=--call
+set call=--graph calls
 goto after_call
 
 
@@ -72,7 +72,7 @@ goto after_call
 :: Run the analyzer
 echo Building and running analyzer!
 
-cargo +%toolchain% run -- %input% %output% %call%
+cargo +%toolchain% run -- --manifest-path %input% --output %output% %call%
 
 
 :: Check whether the toolchain was installed specifically for this, and ask whether to remove it again if it was
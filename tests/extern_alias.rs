@@ -0,0 +1,31 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Calls into another crate are annotated with the Result alias of that crate,
+/// also if the error type is the default of a parameter of the alias (e.g. `anyhow::Result<T, E = Error>`).
+#[test]
+fn extern_alias_with_default_error() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/extern_alias");
+    let output_path =
+        std::env::temp_dir().join(format!("extern-alias-{}.json", std::process::id()));
+
+    let status = Command::new(env!("CARGO_BIN_EXE_cargo-error-chains"))
+        .current_dir(&fixture)
+        .args(["--format", "json", "--graph", "calls", "--output"])
+        .arg(&output_path)
+        .status()
+        .expect("Could not run the analyzer!");
+    assert!(status.code().is_some_and(|code| code <= 1), "{status}");
+
+    let output = std::fs::read_to_string(&output_path).expect("Could not read the output!");
+    let _ = std::fs::remove_file(&output_path);
+    let graph: serde_json::Value = serde_json::from_str(&output).expect("Invalid output!");
+
+    let load = graph["edges"]
+        .as_array()
+        .expect("Output has no edges!")
+        .iter()
+        .find(|edge| edge["callee"] == "errors::load")
+        .expect("Call to errors::load is missing!");
+    assert_eq!(load["alias"], "errors::Result");
+}
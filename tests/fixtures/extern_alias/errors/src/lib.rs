@@ -0,0 +1,9 @@
+/// An error type with a Result alias whose error parameter has a default, like `anyhow::Result`.
+#[derive(Debug)]
+pub struct Error;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+pub fn load() -> Result<String> {
+    Err(Error)
+}
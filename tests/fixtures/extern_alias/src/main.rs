@@ -0,0 +1,8 @@
+fn run() -> errors::Result<String> {
+    let value = errors::load()?;
+    Ok(value)
+}
+
+fn main() {
+    let _ = run();
+}